@@ -0,0 +1,352 @@
+//! A local stand-in for a [Countly](https://count.ly/) server, to test SDK transports end-to-end.
+//!
+//! [MockServer::start] runs an HTTP server on a random local port in a background thread. It accepts the write API
+//! (`/i` and `/i/bulk`, via GET or POST) and the SDK read API (`/o/sdk`), parses everything it receives into typed
+//! [Record]s and offers assertions on them. Failures can be injected to exercise retry logic. Other endpoints, like
+//! those of the read and management APIs, answer what [MockServer::route] sets up.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use countly_mock::MockServer;
+//!
+//! let server = MockServer::start();
+//! server.fail_next(2, 503);
+//! // ... point the SDK at server.url() and record an event ...
+//! assert!(server.wait_for(Duration::from_secs(5), |server| !server.events().is_empty()));
+//! server.assert_event("purchase");
+//! ```
+
+mod http;
+mod records;
+
+pub use records::{Consent, Crash, Event, Record, Request, Session, SessionKind, UserDetails};
+
+use std::{
+    collections::VecDeque,
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, MutexGuard, atomic::{AtomicBool, Ordering}},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use self::http::HttpRequest;
+
+#[derive(Default)]
+struct State {
+    requests: Vec<Request>,
+    records: Vec<Record>,
+    /// Status codes to answer the next requests with, instead of accepting them.
+    failures: VecDeque<u16>,
+    /// Reject every request with this status code.
+    fail_all: Option<u16>,
+    rejected: usize,
+    salt: Option<String>,
+    remote_config: Map<String, Value>,
+    routes: Vec<(String, Box<Route>)>,
+}
+
+type Route = dyn Fn(&Request) -> Value + Send;
+
+/// See the crate documentation.
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts listening on a random port on localhost.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed binding the mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let state = state.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        handle(&state, stream);
+                    }
+                }
+            })
+        };
+        Self { url, state, running, thread: Some(thread) }
+    }
+
+    /// Base URL to configure the SDK with, like `http://127.0.0.1:12345`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Answers the next `count` requests with `status` without accepting them.
+    pub fn fail_next(&self, count: usize, status: u16) {
+        self.lock().failures.extend(std::iter::repeat_n(status, count));
+    }
+
+    /// Answers all requests with `status` until called again with `None`.
+    pub fn fail_all(&self, status: Option<u16>) {
+        self.lock().fail_all = status;
+    }
+
+    /// Rejects write requests without a valid `checksum256` for this salt, like an app with tampering protection.
+    pub fn require_checksum(&self, salt: &str) {
+        self.lock().salt = Some(salt.to_owned());
+    }
+
+    /// Values returned by remote config requests to `/o/sdk`.
+    pub fn set_remote_config(&self, values: Map<String, Value>) {
+        self.lock().remote_config = values;
+    }
+
+    /// Answers requests to `path` with what `handler` returns for them. They are listed in [MockServer::requests], but
+    /// not parsed into records.
+    pub fn route(&self, path: &str, handler: impl Fn(&Request) -> Value + Send + 'static) {
+        self.lock().routes.push((path.trim_end_matches('/').to_owned(), Box::new(handler)));
+    }
+
+    /// Forgets everything received so far.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.requests.clear();
+        state.records.clear();
+        state.rejected = 0;
+    }
+
+    /// All accepted requests, in the order they arrived. Bulk requests are split into their parts.
+    pub fn requests(&self) -> Vec<Request> {
+        self.lock().requests.clone()
+    }
+
+    /// Everything reported in the accepted requests.
+    pub fn records(&self) -> Vec<Record> {
+        self.lock().records.clone()
+    }
+
+    /// Number of requests answered with an error, because of injected failures or a bad checksum.
+    pub fn rejected(&self) -> usize {
+        self.lock().rejected
+    }
+
+    pub fn sessions(&self) -> Vec<Session> {
+        self.records().into_iter().filter_map(|record| match record {
+            Record::Session(session) => Some(session),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.records().into_iter().filter_map(|record| match record {
+            Record::Event(event) => Some(event),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn user_details(&self) -> Vec<UserDetails> {
+        self.records().into_iter().filter_map(|record| match record {
+            Record::UserDetails(details) => Some(details),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn crashes(&self) -> Vec<Crash> {
+        self.records().into_iter().filter_map(|record| match record {
+            Record::Crash(crash) => Some(crash),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn consents(&self) -> Vec<Consent> {
+        self.records().into_iter().filter_map(|record| match record {
+            Record::Consent(consent) => Some(consent),
+            _ => None,
+        }).collect()
+    }
+
+    /// Polls `predicate` until it returns true or `timeout` expires, returning the last result.
+    pub fn wait_for(&self, timeout: Duration, predicate: impl Fn(&Self) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if predicate(self) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Panics unless an event with this key was received, otherwise returns the first one.
+    pub fn assert_event(&self, key: &str) -> Event {
+        let events = self.events();
+        match events.iter().find(|event| event.key == key) {
+            Some(event) => event.clone(),
+            None => panic!("No event {:?} received, got {:?}", key, events.iter().map(|event| &event.key).collect::<Vec<_>>()),
+        }
+    }
+
+    /// Panics unless exactly `count` events with this key were received.
+    pub fn assert_event_count(&self, key: &str, count: usize) {
+        let received = self.events().iter().filter(|event| event.key == key).count();
+        assert_eq!(received, count, "Expected {} events {:?}, received {}", count, key, received);
+    }
+
+    /// Panics unless a session of this kind was reported for this device.
+    pub fn assert_session(&self, device_id: &str, kind: SessionKind) -> Session {
+        let sessions = self.sessions();
+        match sessions.iter().find(|session| session.device_id == device_id && session.kind == kind) {
+            Some(session) => session.clone(),
+            None => panic!("No {:?} session for {:?} received, got {:?}", kind, device_id, sessions),
+        }
+    }
+
+    /// Panics unless the custom property `key` was reported for this device, returning the last reported value.
+    pub fn assert_user_property(&self, device_id: &str, key: &str) -> Value {
+        self.user_details().iter().rev()
+            .filter(|details| details.device_id == device_id)
+            .find_map(|details| details.custom().and_then(|custom| custom.get(key)).cloned())
+            .unwrap_or_else(|| panic!("No user property {:?} received for {:?}", key, device_id))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        // Wake up the accept loop so it notices.
+        let _ = TcpStream::connect(self.url.trim_start_matches("http://"));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, stream: TcpStream) {
+    let request = match http::read_request(&stream) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+    let (status, body) = respond(&mut state.lock().unwrap_or_else(|err| err.into_inner()), &request);
+    let _ = http::write_response(&stream, status, &body);
+}
+
+fn params(request: &HttpRequest) -> Vec<(String, String)> {
+    let mut params: Vec<(String, String)> = form_urlencoded::parse(request.query.as_bytes()).into_owned().collect();
+    if request.method == "POST" {
+        let is_json = request.headers.get("content-type").map(|kind| kind.contains("json")).unwrap_or(false);
+        if is_json {
+            if let Ok(Value::Object(body)) = serde_json::from_str(&request.body) {
+                params.extend(body.into_iter().map(|(key, value)| match value {
+                    Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                }));
+            }
+        } else {
+            params.extend(form_urlencoded::parse(request.body.as_bytes()).into_owned());
+        }
+    }
+    params
+}
+
+/// Verifies `checksum256` the way the server does: the hash covers everything before the checksum parameter.
+fn checksum_valid(request: &HttpRequest, salt: &str) -> bool {
+    let payload = if request.method == "POST" && !request.body.is_empty() { &request.body } else { &request.query };
+    let idx = match payload.find("&checksum256=") {
+        Some(idx) => idx,
+        None => return false,
+    };
+    let checksum = &payload[idx + "&checksum256=".len()..];
+    let checksum = checksum.split('&').next().unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(&payload.as_bytes()[..idx]);
+    hasher.update(salt.as_bytes());
+    let expected: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    expected.eq_ignore_ascii_case(checksum)
+}
+
+fn respond(state: &mut State, request: &HttpRequest) -> (u16, String) {
+    let path = request.path.trim_end_matches('/');
+    let write = path == "/i" || path == "/i/bulk";
+    let routed = state.routes.iter().any(|(route, _)| route == path);
+    if !write && !routed && path != "/o/sdk" {
+        return (404, json!({ "result": "Not found" }).to_string());
+    }
+    if let Some(status) = state.fail_all.or_else(|| state.failures.pop_front()) {
+        state.rejected += 1;
+        return (status, json!({ "result": "Injected failure" }).to_string());
+    }
+    if let (true, Some(salt)) = (write, &state.salt) {
+        if !checksum_valid(request, salt) {
+            state.rejected += 1;
+            return (400, json!({ "result": "Request does not match checksum" }).to_string());
+        }
+    }
+
+    let params = params(request);
+    if let Some((_, handler)) = state.routes.iter().find(|(route, _)| route == path) {
+        let request = Request { endpoint: path.to_owned(), params: params.into_iter().collect() };
+        let answer = handler(&request);
+        state.requests.push(request);
+        return (200, answer.to_string());
+    }
+    if path == "/i/bulk" {
+        let bulk = params.iter()
+            .find(|(key, _)| key == "requests")
+            .and_then(|(_, requests)| serde_json::from_str::<Vec<Map<String, Value>>>(requests).ok());
+        let bulk = match bulk {
+            Some(bulk) => bulk,
+            None => {
+                state.rejected += 1;
+                return (400, json!({ "result": "Missing parameter \"requests\"" }).to_string());
+            }
+        };
+        for part in bulk {
+            let mut part: Vec<(String, String)> = part.into_iter().map(|(key, value)| match value {
+                Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            }).collect();
+            // Parameters of the bulk request itself (like app_key) apply to every part that doesn't override them.
+            for (key, value) in &params {
+                if key != "requests" && !part.iter().any(|(k, _)| k == key) {
+                    part.push((key.clone(), value.clone()));
+                }
+            }
+            accept(state, "/i/bulk", part);
+        }
+        return (200, json!({ "result": "Success" }).to_string());
+    }
+
+    if path == "/o/sdk" {
+        let get = |name: &str| params.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+        let keys: Option<Vec<String>> = get("keys").and_then(|keys| serde_json::from_str(&keys).ok());
+        let omit: Option<Vec<String>> = get("omit_keys").and_then(|keys| serde_json::from_str(&keys).ok());
+        let values: Map<String, Value> = state.remote_config.iter()
+            .filter(|(key, _)| keys.as_ref().map(|keys| keys.contains(key)).unwrap_or(true))
+            .filter(|(key, _)| !omit.as_ref().map(|omit| omit.contains(key)).unwrap_or(false))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        accept(state, "/o/sdk", params);
+        return (200, Value::Object(values).to_string());
+    }
+
+    accept(state, "/i", params);
+    (200, json!({ "result": "Success" }).to_string())
+}
+
+fn accept(state: &mut State, endpoint: &str, params: Vec<(String, String)>) {
+    let request = Request { endpoint: endpoint.to_owned(), params: params.into_iter().collect() };
+    if endpoint != "/o/sdk" {
+        state.records.extend(records::parse(&request));
+    }
+    state.requests.push(request);
+}
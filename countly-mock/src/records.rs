@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// A request the server accepted, with its parameters from the query string and form body combined.
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// `/i`, `/i/bulk` (one entry per bundled request), `/o/sdk` or a path set up with
+    /// [MockServer::route](crate::MockServer::route).
+    pub endpoint: String,
+    pub params: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(String::as_str)
+    }
+
+    pub fn app_key(&self) -> &str {
+        self.get("app_key").unwrap_or_default()
+    }
+
+    pub fn device_id(&self) -> &str {
+        self.get("device_id").unwrap_or_default()
+    }
+
+    /// Milliseconds since the epoch, as reported by the SDK.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.get("timestamp").and_then(|timestamp| timestamp.parse().ok())
+    }
+
+    fn json(&self, key: &str) -> Option<Value> {
+        self.get(key).and_then(|value| serde_json::from_str(value).ok())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionKind {
+    Begin,
+    Extend,
+    End,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub device_id: String,
+    pub kind: SessionKind,
+    /// Seconds reported with `session_duration`.
+    pub duration: Option<f64>,
+    /// Only sent with [SessionKind::Begin].
+    pub metrics: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    #[serde(skip)]
+    pub device_id: String,
+    pub key: String,
+    #[serde(default)]
+    pub count: f64,
+    pub sum: Option<f64>,
+    pub dur: Option<f64>,
+    #[serde(default)]
+    pub segmentation: Map<String, Value>,
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserDetails {
+    pub device_id: String,
+    pub details: Map<String, Value>,
+}
+
+impl UserDetails {
+    /// Custom properties, including modifiers like `{"$inc": 1}`.
+    pub fn custom(&self) -> Option<&Map<String, Value>> {
+        self.details.get("custom").and_then(Value::as_object)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Crash {
+    pub device_id: String,
+    pub crash: Map<String, Value>,
+}
+
+impl Crash {
+    pub fn error(&self) -> &str {
+        self.crash.get("_error").and_then(Value::as_str).unwrap_or_default()
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        !self.crash.get("_nonfatal").and_then(Value::as_bool).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Consent {
+    pub device_id: String,
+    pub features: HashMap<String, bool>,
+}
+
+/// Something an SDK reported, extracted from the parameters of a request. One request can contain several.
+#[derive(Debug, Clone)]
+pub enum Record {
+    Session(Session),
+    Event(Event),
+    UserDetails(UserDetails),
+    Crash(Crash),
+    Consent(Consent),
+    /// A device id change with `old_device_id`, which makes the server merge both users.
+    Merge { old_device_id: String, device_id: String },
+    /// A location reported with `location`, `city`, `country_code` or `ip_address`, or disabled with an empty `location`.
+    Location { device_id: String, params: HashMap<String, String> },
+}
+
+pub(crate) fn parse(request: &Request) -> Vec<Record> {
+    let device_id = request.device_id().to_owned();
+    let mut records = Vec::new();
+
+    if request.get("begin_session").is_some() {
+        records.push(Record::Session(Session {
+            device_id: device_id.clone(),
+            kind: SessionKind::Begin,
+            duration: None,
+            metrics: request.json("metrics").and_then(|metrics| metrics.as_object().cloned()),
+        }));
+    }
+    let duration = request.get("session_duration").and_then(|duration| duration.parse().ok());
+    if request.get("end_session").is_some() {
+        records.push(Record::Session(Session { device_id: device_id.clone(), kind: SessionKind::End, duration, metrics: None }));
+    } else if duration.is_some() {
+        records.push(Record::Session(Session { device_id: device_id.clone(), kind: SessionKind::Extend, duration, metrics: None }));
+    }
+
+    if let Some(events) = request.json("events").and_then(|events| serde_json::from_value::<Vec<Event>>(events).ok()) {
+        records.extend(events.into_iter().map(|mut event| {
+            event.device_id = device_id.clone();
+            Record::Event(event)
+        }));
+    }
+    if let Some(details) = request.json("user_details").and_then(|details| details.as_object().cloned()) {
+        records.push(Record::UserDetails(UserDetails { device_id: device_id.clone(), details }));
+    }
+    if let Some(crash) = request.json("crash").and_then(|crash| crash.as_object().cloned()) {
+        records.push(Record::Crash(Crash { device_id: device_id.clone(), crash }));
+    }
+    if let Some(consent) = request.json("consent").and_then(|consent| serde_json::from_value(consent).ok()) {
+        records.push(Record::Consent(Consent { device_id: device_id.clone(), features: consent }));
+    }
+    if let Some(old_device_id) = request.get("old_device_id") {
+        records.push(Record::Merge { old_device_id: old_device_id.to_owned(), device_id: device_id.clone() });
+    }
+    let location: HashMap<String, String> = ["location", "city", "country_code", "ip_address"].iter()
+        .filter_map(|key| request.get(key).map(|value| ((*key).to_owned(), value.to_owned())))
+        .collect();
+    if !location.is_empty() {
+        records.push(Record::Location { device_id, params: location });
+    }
+    records
+}
@@ -0,0 +1,95 @@
+//! Changes applied to SDK requests before they are forwarded.
+
+use std::net::IpAddr;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use crate::{IpMode, RelayConfig};
+
+/// Parameters the relay computes itself, values sent by the client are dropped.
+const SIGNATURES: &[&str] = &["checksum", "checksum256"];
+
+/// Zeroes the host part of an address: the last octet of IPv4 and the last 80 bits of IPv6 addresses. This keeps
+/// country and city level geolocation working.
+pub fn anonymize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(ip) => {
+            let mut segments = ip.segments();
+            segments[3..].iter_mut().for_each(|segment| *segment = 0);
+            IpAddr::from(segments)
+        }
+    }
+}
+
+/// What the relay knows about the client a request came from.
+pub(crate) struct Client {
+    pub ip: Option<IpAddr>,
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+}
+
+fn set_default(params: &mut Vec<(String, String)>, key: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        if !params.iter().any(|(k, _)| k == key) {
+            params.push((key.to_owned(), value.to_owned()));
+        }
+    }
+}
+
+/// Adds what the relay knows about the client to the parameters of a single request, without overriding what the SDK
+/// provided itself.
+pub(crate) fn enrich(params: &mut Vec<(String, String)>, client: &Client, config: &RelayConfig) {
+    params.retain(|(key, _)| !SIGNATURES.contains(&key.as_str()));
+    match config.ip_mode {
+        IpMode::Forward => set_default(params, "ip_address", client.ip.map(|ip| ip.to_string()).as_deref()),
+        IpMode::Anonymize => {
+            let given = params.iter().find(|(k, _)| k == "ip_address").and_then(|(_, ip)| ip.parse().ok());
+            params.retain(|(k, _)| k != "ip_address");
+            set_default(params, "ip_address", given.or(client.ip).map(|ip| anonymize(ip).to_string()).as_deref());
+        }
+        IpMode::Drop => params.retain(|(k, _)| k != "ip_address"),
+    }
+    set_default(params, "country_code", client.country_code.as_deref());
+    set_default(params, "city", client.city.as_deref());
+}
+
+/// Like [enrich], for every request contained in the `requests` parameter of a bulk request.
+pub(crate) fn enrich_bulk(params: &mut Vec<(String, String)>, client: &Client, config: &RelayConfig) {
+    params.retain(|(key, _)| !SIGNATURES.contains(&key.as_str()));
+    for (key, value) in params.iter_mut() {
+        if key != "requests" {
+            continue;
+        }
+        let requests: Vec<Map<String, Value>> = match serde_json::from_str(value) {
+            Ok(requests) => requests,
+            Err(_) => continue,
+        };
+        let requests: Vec<Map<String, Value>> = requests.into_iter().map(|request| {
+            let mut params: Vec<(String, String)> = request.into_iter().map(|(key, value)| match value {
+                Value::String(value) => (key, value),
+                value => (key, value.to_string()),
+            }).collect();
+            enrich(&mut params, client, config);
+            params.into_iter().map(|(key, value)| (key, Value::String(value))).collect()
+        }).collect();
+        *value = serde_json::to_string(&requests).unwrap_or_default();
+    }
+}
+
+/// URL encodes the parameters, followed by `checksum256` if a salt is configured.
+pub(crate) fn encode(params: &[(String, String)], salt: Option<&str>) -> String {
+    let query = form_urlencoded::Serializer::new(String::new()).extend_pairs(params.iter()).finish();
+    match salt {
+        Some(salt) => {
+            let mut hasher = Sha256::new();
+            hasher.update(query.as_bytes());
+            hasher.update(salt.as_bytes());
+            let checksum: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("{}&checksum256={}", query, checksum)
+        }
+        None => query,
+    }
+}
@@ -0,0 +1,126 @@
+//! Queue of requests waiting to be forwarded, sent in order by a background thread.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+use ureq::Agent;
+use crate::{EventHandler, RelayEvent};
+
+/// The wait after a failure doubles up to this.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A request to forward: the path on the upstream server and the encoded form body.
+pub(crate) struct Forward {
+    pub path: &'static str,
+    pub body: String,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// With a sequence number, to recognize the request being sent after older ones were dropped.
+    requests: VecDeque<(u64, Forward)>,
+    next_id: u64,
+    stopped: bool,
+}
+
+/// Accepts requests from the connection threads and forwards them upstream.
+#[derive(Clone)]
+pub(crate) struct Forwarder {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    limit: usize,
+    on_event: Option<EventHandler>,
+}
+
+impl Forwarder {
+    pub fn start(
+        upstream: String,
+        agent: Agent,
+        limit: usize,
+        retry_delay: Duration,
+        on_event: Option<EventHandler>,
+    ) -> Self {
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let forwarder = Self { queue, limit, on_event };
+        let worker = forwarder.clone();
+        thread::spawn(move || worker.run(&upstream, &agent, retry_delay));
+        forwarder
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Queues a request, dropping the oldest one if the queue is full.
+    pub fn push(&self, request: Forward) {
+        let mut queue = self.lock();
+        if queue.requests.len() >= self.limit {
+            queue.requests.pop_front();
+            EventHandler::report(&self.on_event, RelayEvent::QueueFull);
+        }
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.requests.push_back((id, request));
+        self.queue.1.notify_one();
+    }
+
+    /// Number of requests waiting to be forwarded.
+    pub fn len(&self) -> usize {
+        self.lock().requests.len()
+    }
+
+    pub fn stop(&self) {
+        self.lock().stopped = true;
+        self.queue.1.notify_one();
+    }
+
+    fn run(&self, upstream: &str, agent: &Agent, retry_delay: Duration) {
+        let mut delay = retry_delay;
+        loop {
+            let (id, path, body) = {
+                let mut queue = self.lock();
+                while queue.requests.is_empty() && !queue.stopped {
+                    queue = self.queue.1.wait(queue).unwrap_or_else(|err| err.into_inner());
+                }
+                if queue.stopped {
+                    return;
+                }
+                let (id, front) = queue.requests.front().unwrap();
+                (*id, front.path, front.body.clone())
+            };
+            let result = agent.post(&format!("{}{}", upstream, path))
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .send(&body);
+            let retry = match result {
+                Ok(response) if response.status().is_success() => false,
+                // The server won't accept it later either.
+                Ok(response) if response.status().is_client_error() && response.status().as_u16() != 429 => {
+                    EventHandler::report(&self.on_event, RelayEvent::Rejected(response.status().as_u16()));
+                    false
+                }
+                Ok(response) => {
+                    let reason = format!("Server returned {}", response.status());
+                    EventHandler::report(&self.on_event, RelayEvent::Retrying { reason, delay });
+                    true
+                }
+                Err(err) => {
+                    let reason = format!("Server not reachable ({})", err);
+                    EventHandler::report(&self.on_event, RelayEvent::Retrying { reason, delay });
+                    true
+                }
+            };
+            if retry {
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_BACKOFF);
+            } else {
+                delay = retry_delay;
+                let mut queue = self.lock();
+                if queue.requests.front().map(|(front, _)| *front == id).unwrap_or(false) {
+                    queue.requests.pop_front();
+                }
+            }
+        }
+    }
+}
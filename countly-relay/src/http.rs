@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+/// Requests with larger bodies are rejected, SDKs switch to bulk requests long before.
+const MAX_BODY: usize = 1 << 20;
+/// Requests with a larger request line and headers are rejected, SDKs send a handful of short headers.
+const MAX_HEAD: u64 = 64 << 10;
+const MAX_HEADERS: usize = 100;
+/// Clients that send nothing for this long are disconnected, so they can't keep a thread busy.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The parts of an HTTP request the relay cares about.
+pub(crate) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    /// Header names in lower case.
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A line of the request head, which has to end before [MAX_HEAD] is reached.
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(invalid("Request head too large or incomplete"));
+    }
+    Ok(line)
+}
+
+/// Reads a single HTTP/1.1 request. Chunked bodies are not supported, no SDK sends them.
+pub(crate) fn read_request(stream: &TcpStream) -> io::Result<HttpRequest> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut head = BufReader::new(stream).take(MAX_HEAD);
+    let line = read_line(&mut head)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = match target.find('?') {
+        Some(idx) => (target[..idx].to_owned(), target[idx + 1..].to_owned()),
+        None => (target.to_owned(), String::new()),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let header = read_line(&mut head)?;
+        if header.trim().is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("Too many headers"));
+        }
+        if let Some(idx) = header.find(':') {
+            headers.insert(header[..idx].trim().to_ascii_lowercase(), header[idx + 1..].trim().to_owned());
+        }
+    }
+
+    let length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(invalid("Request body too large"));
+    }
+    let mut body = vec![0; length];
+    head.into_inner().read_exact(&mut body)?;
+    Ok(HttpRequest {
+        method,
+        path,
+        query,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Writes a JSON response. CORS is allowed from everywhere, since the Web SDK runs on other origins.
+pub(crate) fn write_response(mut stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        502 => "Bad Gateway",
+        _ => "Unknown",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{}",
+        status, reason, body.len(), body,
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,447 @@
+//! A first-party relay for [Countly](https://count.ly/) SDK traffic.
+//!
+//! Requests to a Countly server are often blocked by ad blockers. Running this relay on your own domain and pointing
+//! the SDK's `url` at it avoids that. The relay accepts the write API (`/i` and `/i/bulk`) and remote config
+//! (`/o/sdk`), optionally anonymizes the client IP address, adds location from headers set by a CDN, signs requests
+//! with the app's salt, and forwards them to the real server. Write requests are acknowledged right away and queued,
+//! so an unreachable server doesn't slow down clients.
+//!
+//! ```no_run
+//! use countly_relay::{Relay, RelayConfig};
+//!
+//! let mut config = RelayConfig::new("0.0.0.0:8080".parse().unwrap(), "https://countly.example.com");
+//! config.country_header = Some("cf-ipcountry".to_owned());
+//! Relay::run(config)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+mod enrich;
+mod forward;
+mod http;
+
+pub use enrich::anonymize;
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}},
+    thread,
+    time::Duration,
+};
+use serde_json::{Value, json};
+use ureq::Agent;
+use self::{
+    enrich::{Client, enrich, enrich_bulk, encode},
+    forward::{Forward, Forwarder},
+    http::HttpRequest,
+};
+
+/// What to tell the server about the IP address of clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpMode {
+    /// Send the full address.
+    Forward,
+    /// Send the address with the host part zeroed, see [anonymize].
+    Anonymize,
+    /// Don't send any address. The server then sees the address of the relay, so location has to come from headers.
+    Drop,
+}
+
+/// Something worth logging that happened while relaying, passed to [RelayConfig::on_event].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayEvent {
+    /// The queue was full, so the oldest request was dropped.
+    QueueFull,
+    /// The server rejected a request with this status, so it was dropped.
+    Rejected(u16),
+    /// Forwarding a request failed, it's sent again after the delay.
+    Retrying { reason: String, delay: Duration },
+    /// A connection was closed right away, since [RelayConfig::max_connections] were open.
+    TooManyConnections,
+}
+
+impl fmt::Display for RelayEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => write!(f, "Queue full, dropping the oldest request"),
+            Self::Rejected(status) => write!(f, "Server rejected a request with {}, dropping it", status),
+            Self::Retrying { reason, delay } => write!(f, "{}, retrying in {:?}", reason, delay),
+            Self::TooManyConnections => write!(f, "Too many connections, refusing one"),
+        }
+    }
+}
+
+/// Receives the [RelayEvent]s of a relay, see [RelayConfig::on_event].
+#[derive(Clone)]
+pub struct EventHandler(Arc<dyn Fn(&RelayEvent) + Send + Sync>);
+
+impl EventHandler {
+    pub fn new(handler: impl Fn(&RelayEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(handler))
+    }
+
+    pub(crate) fn report(handler: &Option<Self>, event: RelayEvent) {
+        if let Some(handler) = handler {
+            (handler.0)(&event);
+        }
+    }
+}
+
+impl fmt::Debug for EventHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventHandler")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// address to listen on
+    pub listen: SocketAddr,
+    /// url of the Countly server to forward to
+    pub upstream: String,
+    /// path the relay is reachable under, if a reverse proxy forwards a sub path like `/analytics` (default: none)
+    pub path_prefix: String,
+    /// what to forward of the client's IP address (default: [IpMode::Anonymize])
+    pub ip_mode: IpMode,
+    /// number of reverse proxies in front of the relay. The client address is taken from `X-Forwarded-For`, as the
+    /// entry this many hops from the right, since every proxy appends the address it got the request from and
+    /// everything further left may be made up by the client (default: 0, the header is ignored)
+    pub trusted_proxies: usize,
+    /// header containing the two-letter country code of the client, like `cf-ipcountry` on Cloudflare (default: none)
+    pub country_header: Option<String>,
+    /// header containing the city of the client (default: none)
+    pub city_header: Option<String>,
+    /// salt of the app, to sign forwarded requests with `checksum256`. Signatures sent by clients are removed, since
+    /// the relay changes the parameters (default: none)
+    pub salt: Option<String>,
+    /// maximum amount of requests waiting to be forwarded, the oldest ones are dropped beyond that (default: 10000)
+    pub queue_size: usize,
+    /// time to wait after the server failed, doubling up to a minute with every further failure (default: 1 second)
+    pub retry_delay: Duration,
+    /// maximum amount of connections handled at once, further ones are closed until one finishes (default: 256)
+    pub max_connections: usize,
+    /// called with events worth logging, the relay doesn't print anything itself (default: none)
+    pub on_event: Option<EventHandler>,
+}
+
+impl RelayConfig {
+    pub fn new(listen: SocketAddr, upstream: &str) -> Self {
+        Self {
+            listen,
+            upstream: upstream.trim_end_matches('/').to_owned(),
+            path_prefix: String::new(),
+            ip_mode: IpMode::Anonymize,
+            trusted_proxies: 0,
+            country_header: None,
+            city_header: None,
+            salt: None,
+            queue_size: 10000,
+            retry_delay: Duration::from_secs(1),
+            max_connections: 256,
+            on_event: None,
+        }
+    }
+}
+
+/// One of the [RelayConfig::max_connections], given back when dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A running relay, see the crate documentation. Stops when dropped.
+pub struct Relay {
+    addr: SocketAddr,
+    forwarder: Forwarder,
+    running: Arc<AtomicBool>,
+}
+
+impl Relay {
+    /// Starts the relay in background threads.
+    pub fn start(config: RelayConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(config.listen)?;
+        let addr = listener.local_addr()?;
+        let agent: Agent = Agent::config_builder().http_status_as_error(false).build().into();
+        let forwarder = Forwarder::start(
+            config.upstream.clone(),
+            agent.clone(),
+            config.queue_size,
+            config.retry_delay,
+            config.on_event.clone(),
+        );
+        let running = Arc::new(AtomicBool::new(true));
+        {
+            let config = Arc::new(config);
+            let forwarder = forwarder.clone();
+            let running = running.clone();
+            let open = Arc::new(AtomicUsize::new(0));
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        // Every connection holds a thread until it's done or times out, so slow clients could
+                        // otherwise use up all threads. Closing the stream is cheaper than answering.
+                        if open.fetch_add(1, Ordering::SeqCst) >= config.max_connections {
+                            open.fetch_sub(1, Ordering::SeqCst);
+                            EventHandler::report(&config.on_event, RelayEvent::TooManyConnections);
+                            continue;
+                        }
+                        let slot = Slot(open.clone());
+                        let (config, forwarder, agent) = (config.clone(), forwarder.clone(), agent.clone());
+                        thread::spawn(move || {
+                            let _slot = slot;
+                            handle(&config, &forwarder, &agent, stream)
+                        });
+                    }
+                }
+            });
+        }
+        Ok(Self { addr, forwarder, running })
+    }
+
+    /// Runs the relay on the current thread, forever.
+    pub fn run(config: RelayConfig) -> io::Result<()> {
+        let _relay = Self::start(config)?;
+        loop {
+            thread::park();
+        }
+    }
+
+    /// The address the relay listens on, useful when listening on port 0.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of requests waiting to be forwarded.
+    pub fn queued(&self) -> usize {
+        self.forwarder.len()
+    }
+}
+
+impl Drop for Relay {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.forwarder.stop();
+        // Wake up the accept loop so it notices.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn handle(config: &RelayConfig, forwarder: &Forwarder, agent: &Agent, stream: TcpStream) {
+    let request = match http::read_request(&stream) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+    let peer = stream.peer_addr().ok().map(|addr| addr.ip());
+    let (status, body) = respond(config, forwarder, agent, &request, peer);
+    let _ = http::write_response(&stream, status, &body);
+}
+
+fn client(config: &RelayConfig, request: &HttpRequest, peer: Option<IpAddr>) -> Client {
+    let forwarded = request.header("x-forwarded-for")
+        .zip(config.trusted_proxies.checked_sub(1))
+        .and_then(|(forwarded, hops)| forwarded.rsplit(',').nth(hops))
+        .and_then(|ip| ip.trim().parse().ok());
+    let header = |name: &Option<String>| name.as_ref()
+        .and_then(|name| request.header(&name.to_ascii_lowercase()))
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned);
+    Client { ip: forwarded.or(peer), country_code: header(&config.country_header), city: header(&config.city_header) }
+}
+
+/// Parameters from the query string and the form or JSON body.
+fn params(request: &HttpRequest) -> Vec<(String, String)> {
+    let mut params: Vec<(String, String)> = form_urlencoded::parse(request.query.as_bytes()).into_owned().collect();
+    if request.method == "POST" {
+        let is_json = request.header("content-type").map(|kind| kind.contains("json")).unwrap_or(false);
+        if is_json {
+            if let Ok(Value::Object(body)) = serde_json::from_str(&request.body) {
+                params.extend(body.into_iter().map(|(key, value)| match value {
+                    Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                }));
+            }
+        } else {
+            params.extend(form_urlencoded::parse(request.body.as_bytes()).into_owned());
+        }
+    }
+    params
+}
+
+fn respond(config: &RelayConfig, forwarder: &Forwarder, agent: &Agent, request: &HttpRequest, peer: Option<IpAddr>) -> (u16, String) {
+    if request.method == "OPTIONS" {
+        return (204, String::new());
+    }
+    if request.method != "GET" && request.method != "POST" {
+        return (405, json!({ "result": "Method not allowed" }).to_string());
+    }
+    let path = request.path.strip_prefix(config.path_prefix.trim_end_matches('/')).unwrap_or("");
+    let path = match path.trim_end_matches('/') {
+        "/i" => "/i",
+        "/i/bulk" => "/i/bulk",
+        "/o/sdk" => "/o/sdk",
+        _ => return (404, json!({ "result": "Not found" }).to_string()),
+    };
+
+    let client = client(config, request, peer);
+    let mut params = params(request);
+    if path == "/i/bulk" {
+        enrich_bulk(&mut params, &client, config);
+    } else {
+        enrich(&mut params, &client, config);
+    }
+    let body = encode(&params, config.salt.as_deref());
+
+    if path == "/o/sdk" {
+        // Remote config is needed right away, so it isn't queued.
+        let result = agent.post(&format!("{}{}", config.upstream, path))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .send(&body)
+            .and_then(|mut response| Ok((response.status().as_u16(), response.body_mut().read_to_string()?)));
+        return match result {
+            Ok(response) => response,
+            Err(err) => (502, json!({ "result": format!("Server not reachable: {}", err) }).to_string()),
+        };
+    }
+    forwarder.push(Forward { path, body });
+    (200, json!({ "result": "Success" }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use countly_mock::MockServer;
+    use super::*;
+
+    fn post(relay: &Relay, path: &str, body: &str, headers: &[(&str, &str)]) -> u16 {
+        let agent: Agent = Agent::config_builder().http_status_as_error(false).build().into();
+        let mut request = agent.post(&format!("http://{}{}", relay.addr(), path))
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send(body).map(|response| response.status().as_u16()).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[test]
+    fn enriches_signs_and_forwards() {
+        let server = MockServer::start();
+        server.require_checksum("salt");
+        server.fail_next(1, 503);
+        let mut config = RelayConfig::new("127.0.0.1:0".parse().unwrap(), server.url());
+        config.path_prefix = "/analytics".to_owned();
+        config.trusted_proxies = 1;
+        config.country_header = Some("CF-IPCountry".to_owned());
+        config.salt = Some("salt".to_owned());
+        config.retry_delay = Duration::from_millis(10);
+        let relay = Relay::start(config).unwrap();
+
+        let events = r#"[{"key":"click","count":1}]"#;
+        let body = format!("app_key=key&device_id=a&events={}&checksum256=forged", events);
+        let headers = [("X-Forwarded-For", "198.51.100.1, 203.0.113.77"), ("CF-IPCountry", "AT")];
+        assert_eq!(post(&relay, "/analytics/i", &body, &headers), 200);
+        let bulk = r#"[{"device_id":"b","events":"[{\"key\":\"buy\",\"count\":1}]","ip_address":"2001:db8::1:2:3:4"}]"#;
+        assert_eq!(post(&relay, "/analytics/i/bulk", &format!("app_key=key&requests={}", bulk), &[]), 200);
+        assert_eq!(post(&relay, "/i", &body, &[]), 404);
+
+        assert!(server.wait_for(Duration::from_secs(5), |server| server.events().len() == 2));
+        assert_eq!(server.rejected(), 1);
+        let requests = server.requests();
+        assert_eq!(requests[0].get("ip_address"), Some("203.0.113.0"));
+        assert_eq!(requests[0].get("country_code"), Some("AT"));
+        assert_eq!(requests[1].get("ip_address"), Some("2001:db8::"));
+        assert_eq!(requests[1].app_key(), "key");
+        server.assert_event("buy");
+        // The forwarder takes a request off the queue only after the server answered.
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while relay.queued() > 0 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(relay.queued(), 0);
+    }
+
+    #[test]
+    fn trusts_forwarded_for_per_proxy() {
+        let mut request = HttpRequest {
+            method: "GET".to_owned(),
+            path: "/i".to_owned(),
+            query: String::new(),
+            headers: Default::default(),
+            body: String::new(),
+        };
+        request.headers.insert("x-forwarded-for".to_owned(), "198.51.100.1, 203.0.113.77, 10.0.0.1".to_owned());
+        let mut config = RelayConfig::new("127.0.0.1:0".parse().unwrap(), "http://localhost");
+        let peer = Some("10.0.0.2".parse().unwrap());
+        let ip = |config: &RelayConfig| client(config, &request, peer).ip.map(|ip| ip.to_string());
+        assert_eq!(ip(&config).as_deref(), Some("10.0.0.2"));
+        config.trusted_proxies = 1;
+        assert_eq!(ip(&config).as_deref(), Some("10.0.0.1"));
+        config.trusted_proxies = 2;
+        assert_eq!(ip(&config).as_deref(), Some("203.0.113.77"));
+        config.trusted_proxies = 4;
+        assert_eq!(ip(&config).as_deref(), Some("10.0.0.2"));
+    }
+
+    #[test]
+    fn rejects_oversized_heads() {
+        let relay = Relay::start(RelayConfig::new("127.0.0.1:0".parse().unwrap(), "http://localhost")).unwrap();
+        let mut stream = TcpStream::connect(relay.addr()).unwrap();
+        let header = format!("X-Padding: {}\r\n", "a".repeat(1000));
+        // Writing may fail once the relay closed the connection.
+        let _ = write!(stream, "GET /o/sdk HTTP/1.1\r\n{}\r\n", header.repeat(100));
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert_eq!(response, "");
+    }
+
+    #[test]
+    fn refuses_connections_above_the_limit() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut config = RelayConfig::new("127.0.0.1:0".parse().unwrap(), "http://localhost");
+        config.max_connections = 1;
+        config.on_event = Some(EventHandler::new({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push(event.clone())
+        }));
+        let relay = Relay::start(config).unwrap();
+
+        // A client that doesn't send anything holds the only connection.
+        let idle = TcpStream::connect(relay.addr()).unwrap();
+        let mut refused = TcpStream::connect(relay.addr()).unwrap();
+        refused.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(refused.read(&mut [0; 1]).unwrap(), 0);
+        assert_eq!(*events.lock().unwrap(), [RelayEvent::TooManyConnections]);
+
+        drop(idle);
+        let agent: Agent = Agent::config_builder().http_status_as_error(false).build().into();
+        let url = format!("http://{}/unknown", relay.addr());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        // The connection is given back once its thread noticed the client left.
+        let status = loop {
+            match agent.get(&url).call() {
+                Ok(response) => break response.status().as_u16(),
+                Err(err) if std::time::Instant::now() > deadline => panic!("{}", err),
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn proxies_remote_config() {
+        let server = MockServer::start();
+        server.set_remote_config(json!({ "color": "red", "size": 2 }).as_object().unwrap().clone());
+        let relay = Relay::start(RelayConfig::new("127.0.0.1:0".parse().unwrap(), server.url())).unwrap();
+
+        let url = format!("http://{}/o/sdk?method=rc&app_key=key&device_id=a&keys=%5B%22color%22%5D", relay.addr());
+        let body = ureq::get(&url).call().unwrap().body_mut().read_to_string().unwrap();
+        assert_eq!(body, r#"{"color":"red"}"#);
+        assert_eq!(server.requests()[0].get("ip_address"), Some("127.0.0.0"));
+        assert_eq!(anonymize("2001:db8:1:2:3:4:5:6".parse().unwrap()).to_string(), "2001:db8:1::");
+    }
+}
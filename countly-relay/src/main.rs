@@ -0,0 +1,103 @@
+//! Runs the relay, see the library documentation.
+
+use std::{env, net::SocketAddr, process};
+use countly_relay::{EventHandler, IpMode, Relay, RelayConfig};
+
+const USAGE: &str = "\
+Usage: countly-relay [OPTIONS] --upstream URL
+
+Options:
+    --upstream URL        url of the Countly server (default: $COUNTLY_URL)
+    --listen ADDR         address to listen on (default: 127.0.0.1:8080)
+    --path-prefix PATH    path the relay is reachable under behind a reverse proxy
+    --ip MODE             forward, anonymize or drop the client IP address (default: anonymize)
+    --trusted-proxies N   number of reverse proxies in front of the relay, to take the client IP address from
+                          X-Forwarded-For (default: 0)
+    --country-header NAME header with the client's country code, like cf-ipcountry
+    --city-header NAME    header with the client's city
+    --salt SALT           sign forwarded requests with this salt (default: $COUNTLY_SALT)
+    --queue-size N        maximum number of queued requests (default: 10000)
+    --max-connections N   maximum number of connections handled at once (default: 256)
+    -h, --help            print this help";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("countly-relay: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn main() {
+    let mut listen: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+    let mut upstream = env::var("COUNTLY_URL").ok();
+    let mut path_prefix = None;
+    let mut ip_mode = IpMode::Anonymize;
+    let mut trusted_proxies = 0;
+    let mut country_header = None;
+    let mut city_header = None;
+    let mut salt = env::var("COUNTLY_SALT").ok().filter(|salt| !salt.is_empty());
+    let mut queue_size = None;
+    let mut max_connections = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--upstream" => upstream = Some(value()),
+            "--listen" => {
+                let addr = value();
+                listen = addr.parse().unwrap_or_else(|_| fail(&format!("Invalid address: {}", addr)));
+            }
+            "--path-prefix" => path_prefix = Some(value()),
+            "--ip" => ip_mode = match value().as_str() {
+                "forward" => IpMode::Forward,
+                "anonymize" => IpMode::Anonymize,
+                "drop" => IpMode::Drop,
+                mode => fail(&format!("Unknown IP mode {}", mode)),
+            },
+            "--trusted-proxies" => {
+                let count = value();
+                trusted_proxies = count.parse().unwrap_or_else(|_| fail(&format!("Invalid number of proxies: {}", count)));
+            }
+            "--country-header" => country_header = Some(value()),
+            "--city-header" => city_header = Some(value()),
+            "--salt" => salt = Some(value()),
+            "--queue-size" => {
+                let size = value();
+                queue_size = Some(size.parse().unwrap_or_else(|_| fail(&format!("Invalid queue size: {}", size))));
+            }
+            "--max-connections" => {
+                let count = value();
+                max_connections =
+                    Some(count.parse().unwrap_or_else(|_| fail(&format!("Invalid number of connections: {}", count))));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("Unknown option {}", arg)),
+        }
+    }
+
+    let upstream = upstream.filter(|url| !url.is_empty()).unwrap_or_else(|| fail("No upstream given"));
+    let mut config = RelayConfig::new(listen, &upstream);
+    if let Some(path_prefix) = path_prefix {
+        config.path_prefix = path_prefix;
+    }
+    if let Some(queue_size) = queue_size {
+        config.queue_size = queue_size;
+    }
+    if let Some(max_connections) = max_connections {
+        config.max_connections = max_connections;
+    }
+    config.ip_mode = ip_mode;
+    config.trusted_proxies = trusted_proxies;
+    config.country_header = country_header;
+    config.city_header = city_header;
+    config.salt = salt;
+    config.on_event = Some(EventHandler::new(|event| eprintln!("[countly-relay] {}", event)));
+
+    eprintln!("[countly-relay] Forwarding {} to {}", listen, config.upstream);
+    if let Err(err) = Relay::run(config) {
+        eprintln!("countly-relay: Can't listen on {}: {}", listen, err);
+        process::exit(1);
+    }
+}
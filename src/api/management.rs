@@ -0,0 +1,355 @@
+//! The management API (`/i/apps`, `/i/events`, `/i/remote-config`), to script the setup of apps.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use super::{ApiClient, ApiError};
+
+/// Settings of an app, for [ApiClient::create_app] and [ApiClient::update_app].
+#[derive(Debug, Clone, Serialize)]
+pub struct AppSettings {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// two-letter country code
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// time zone name, like `Europe/Vienna`
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// category id as shown in the dashboard
+    pub category: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    /// `web`, `mobile` or `desktop` (default: mobile)
+    pub app_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// the app key the SDKs use, will be generated if not provided
+    pub key: Option<String>,
+}
+
+impl AppSettings {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_owned(), country: None, timezone: None, category: None, app_type: None, key: None }
+    }
+}
+
+/// An app on the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct App {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub name: String,
+    /// The app key the SDKs use.
+    pub key: String,
+    pub country: Option<String>,
+    pub timezone: Option<String>,
+    pub category: Option<String>,
+    #[serde(rename = "type")]
+    pub app_type: Option<String>,
+}
+
+/// How an event is shown in the dashboard, see [ApiClient::set_event_metadata].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// display name
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// label of the count
+    pub count: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// label of the sum
+    pub sum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// label of the duration
+    pub dur: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_visible: Option<bool>,
+}
+
+/// A remote config parameter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parameter {
+    #[serde(rename = "_id", skip_serializing)]
+    /// assigned by the server
+    pub id: Option<String>,
+    #[serde(rename = "parameter_key")]
+    pub key: String,
+    pub default_value: Json,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    /// values that override the default for users matching a condition, the first matching one wins
+    pub conditions: Vec<ParameterCondition>,
+}
+
+impl Parameter {
+    pub fn new(key: &str, default_value: Json) -> Self {
+        Self { id: None, key: key.to_owned(), default_value, description: String::new(), conditions: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParameterCondition {
+    /// [Condition::id]
+    pub condition_id: String,
+    pub value: Json,
+}
+
+/// A group of users that gets different remote config values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    #[serde(rename = "_id", skip_serializing)]
+    /// assigned by the server
+    pub id: Option<String>,
+    #[serde(rename = "condition_name")]
+    pub name: String,
+    #[serde(rename = "condition_color", default)]
+    /// color index in the dashboard
+    pub color: u32,
+    #[serde(rename = "condition")]
+    /// the MongoDB query selecting the users, like `{"up.cc": {"$in": ["AT"]}}`
+    pub query: Json,
+    #[serde(rename = "condition_definition", default)]
+    /// human readable version of the query, shown in the dashboard
+    pub definition: String,
+    #[serde(rename = "seed_value", default)]
+    /// seed for conditions assigning users randomly
+    pub seed: String,
+}
+
+impl Condition {
+    pub fn new(name: &str, query: Json) -> Self {
+        Self { id: None, name: name.to_owned(), color: 1, definition: query.to_string(), query, seed: String::new() }
+    }
+}
+
+/// All remote config parameters and conditions of an app, see [ApiClient::remote_config_settings].
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RemoteConfigSettings {
+    #[serde(default)]
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+/// `/o/apps/mine` answer, apps by id.
+#[derive(Deserialize)]
+struct Apps {
+    #[serde(default)]
+    admin_of: HashMap<String, App>,
+    #[serde(default)]
+    user_of: HashMap<String, App>,
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+impl ApiClient {
+    /// All apps the API key has access to.
+    pub fn apps(&self) -> Result<Vec<App>, ApiError> {
+        let apps: Apps = self.get("/o/apps/mine", &[])?;
+        let mut apps: Vec<App> = apps.admin_of.into_iter().chain(apps.user_of).map(|(_, app)| app).collect();
+        apps.sort_by(|a, b| a.id.cmp(&b.id));
+        apps.dedup_by(|a, b| a.id == b.id);
+        Ok(apps)
+    }
+
+    /// Creates a new app. Use [ApiClient::for_app] to set it up further.
+    pub fn create_app(&self, settings: &AppSettings) -> Result<App, ApiError> {
+        self.post("/i/apps/create", &[("args", &to_json(settings))])
+    }
+
+    pub fn update_app(&self, app_id: &str, settings: &AppSettings) -> Result<(), ApiError> {
+        let mut args = serde_json::to_value(settings).unwrap_or_default();
+        args["app_id"] = Json::from(app_id);
+        self.post::<Json>("/i/apps/update", &[("args", &args.to_string())]).map(|_| ())
+    }
+
+    /// Deletes an app with all of its data.
+    pub fn delete_app(&self, app_id: &str) -> Result<(), ApiError> {
+        let args = serde_json::json!({ "app_id": app_id });
+        self.post::<Json>("/i/apps/delete", &[("args", &args.to_string())]).map(|_| ())
+    }
+
+    /// Sets display names, descriptions and visibility of events, by event key.
+    pub fn set_event_metadata(&self, metadata: &HashMap<String, EventMetadata>) -> Result<(), ApiError> {
+        self.post::<Json>("/i/events/edit_map", &[("event_map", &to_json(metadata))]).map(|_| ())
+    }
+
+    /// Sets the segments that are not stored for each event key, to keep the database small.
+    pub fn set_omitted_segments(&self, segments: &HashMap<String, Vec<String>>) -> Result<(), ApiError> {
+        self.post::<Json>("/i/events/edit_map", &[("omitted_segments", &to_json(segments))]).map(|_| ())
+    }
+
+    /// Shows or hides events in the dashboard.
+    pub fn set_events_visible(&self, keys: &[&str], visible: bool) -> Result<(), ApiError> {
+        let visibility = if visible { "show" } else { "hide" };
+        self.post::<Json>("/i/events/change_visibility", &[("events", &to_json(&keys)), ("set_visibility", visibility)])
+            .map(|_| ())
+    }
+
+    /// Deletes events with all of their data.
+    pub fn delete_events(&self, keys: &[&str]) -> Result<(), ApiError> {
+        self.post::<Json>("/i/events/delete_events", &[("events", &to_json(&keys))]).map(|_| ())
+    }
+
+    /// All remote config parameters and conditions.
+    pub fn remote_config_settings(&self) -> Result<RemoteConfigSettings, ApiError> {
+        self.get("/o", &[("method", "remote-config")])
+    }
+
+    /// Creates a parameter, returning it with its id.
+    pub fn add_parameter(&self, parameter: &Parameter) -> Result<Parameter, ApiError> {
+        self.post::<Json>("/i/remote-config/add-parameter", &[("parameter", &to_json(parameter))])?;
+        // The server doesn't return the id, but keys are unique.
+        self.remote_config_settings()?.parameters.into_iter()
+            .find(|added| added.key == parameter.key)
+            .ok_or_else(|| ApiError::Decode(format!("Parameter {} missing after adding it", parameter.key)))
+    }
+
+    /// Replaces the parameter with the id of `parameter`, which has to be set.
+    pub fn update_parameter(&self, parameter: &Parameter) -> Result<(), ApiError> {
+        let id = parameter.id.as_deref()
+            .ok_or_else(|| ApiError::Invalid(format!("Parameter {} has no id to update", parameter.key)))?;
+        self.post::<Json>("/i/remote-config/update-parameter", &[("parameter_id", id), ("parameter", &to_json(parameter))])
+            .map(|_| ())
+    }
+
+    pub fn remove_parameter(&self, parameter_id: &str) -> Result<(), ApiError> {
+        self.post::<Json>("/i/remote-config/remove-parameter", &[("parameter_id", parameter_id)]).map(|_| ())
+    }
+
+    /// Creates a condition, returning it with its id to use in [ParameterCondition].
+    pub fn add_condition(&self, condition: &Condition) -> Result<Condition, ApiError> {
+        self.post::<Json>("/i/remote-config/add-condition", &[("condition", &to_json(condition))])?;
+        // The server doesn't return the id, but names are unique.
+        self.remote_config_settings()?.conditions.into_iter()
+            .find(|added| added.name == condition.name)
+            .ok_or_else(|| ApiError::Decode(format!("Condition {} missing after adding it", condition.name)))
+    }
+
+    /// Replaces the condition with the id of `condition`, which has to be set.
+    pub fn update_condition(&self, condition: &Condition) -> Result<(), ApiError> {
+        let id = condition.id.as_deref()
+            .ok_or_else(|| ApiError::Invalid(format!("Condition {} has no id to update", condition.name)))?;
+        self.post::<Json>("/i/remote-config/update-condition", &[("condition_id", id), ("condition", &to_json(condition))])
+            .map(|_| ())
+    }
+
+    /// Removes a condition, parameters no longer use the values they had for it.
+    pub fn remove_condition(&self, condition_id: &str) -> Result<(), ApiError> {
+        self.post::<Json>("/i/remote-config/remove-condition", &[("condition_id", condition_id)]).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use countly_mock::MockServer;
+    use serde_json::json;
+    use crate::Config;
+    use super::*;
+
+    #[derive(Default)]
+    struct Settings {
+        parameters: Vec<Json>,
+        conditions: Vec<Json>,
+        last_id: u32,
+    }
+
+    /// Keeps remote config like the server: adding assigns ids, updating replaces by id and removing drops by id.
+    fn remote_config(server: &MockServer) {
+        let settings = Arc::new(Mutex::new(Settings::default()));
+        let route = |path: &str, change: fn(&mut Settings, &countly_mock::Request)| {
+            let settings = settings.clone();
+            server.route(path, move |request| {
+                change(&mut settings.lock().unwrap(), request);
+                json!({ "result": "Success" })
+            });
+        };
+        fn parse(request: &countly_mock::Request, key: &str) -> Json {
+            serde_json::from_str(request.get(key).unwrap()).unwrap()
+        }
+        fn replace(items: &mut [Json], id: &str, mut item: Json) {
+            let current = items.iter_mut().find(|current| current["_id"] == id).unwrap();
+            item["_id"] = current["_id"].clone();
+            *current = item;
+        }
+        route("/i/remote-config/add-parameter", |settings, request| {
+            settings.last_id += 1;
+            let mut parameter = parse(request, "parameter");
+            parameter["_id"] = json!(format!("p{}", settings.last_id));
+            settings.parameters.push(parameter);
+        });
+        route("/i/remote-config/update-parameter", |settings, request| {
+            replace(&mut settings.parameters, request.get("parameter_id").unwrap(), parse(request, "parameter"));
+        });
+        route("/i/remote-config/remove-parameter", |settings, request| {
+            settings.parameters.retain(|parameter| parameter["_id"] != request.get("parameter_id").unwrap());
+        });
+        route("/i/remote-config/add-condition", |settings, request| {
+            settings.last_id += 1;
+            let mut condition = parse(request, "condition");
+            condition["_id"] = json!(format!("c{}", settings.last_id));
+            settings.conditions.push(condition);
+        });
+        route("/i/remote-config/update-condition", |settings, request| {
+            replace(&mut settings.conditions, request.get("condition_id").unwrap(), parse(request, "condition"));
+        });
+        route("/i/remote-config/remove-condition", |settings, request| {
+            settings.conditions.retain(|condition| condition["_id"] != request.get("condition_id").unwrap());
+        });
+        server.route("/o", move |_| {
+            let settings = settings.lock().unwrap();
+            json!({ "parameters": settings.parameters, "conditions": settings.conditions })
+        });
+    }
+
+    #[test]
+    fn manages_remote_config() {
+        let server = MockServer::start();
+        remote_config(&server);
+        let client = ApiClient::new(&Config::new("key", server.url()), "secret", "app");
+
+        let mut condition = client.add_condition(&Condition::new("Austria", json!({ "up.cc": { "$in": ["AT"] } }))).unwrap();
+        assert_eq!(condition.id.as_deref(), Some("c1"));
+        let mut parameter = Parameter::new("color", json!("red"));
+        parameter.conditions.push(ParameterCondition { condition_id: "c1".to_owned(), value: json!("white") });
+        let mut parameter = client.add_parameter(&parameter).unwrap();
+        assert_eq!(parameter.id.as_deref(), Some("p2"));
+        assert_eq!(parameter.conditions[0].value, json!("white"));
+
+        condition.name = "Austria and Germany".to_owned();
+        client.update_condition(&condition).unwrap();
+        parameter.default_value = json!("blue");
+        client.update_parameter(&parameter).unwrap();
+        let settings = client.remote_config_settings().unwrap();
+        assert_eq!(settings.conditions[0].name, "Austria and Germany");
+        assert_eq!(settings.parameters[0].default_value, json!("blue"));
+        assert_eq!(settings.parameters[0].id.as_deref(), Some("p2"));
+
+        client.remove_parameter("p2").unwrap();
+        client.remove_condition("c1").unwrap();
+        let settings = client.remote_config_settings().unwrap();
+        assert!(settings.parameters.is_empty() && settings.conditions.is_empty());
+        let request = server.requests().into_iter().find(|request| request.endpoint == "/i/remote-config/remove-condition").unwrap();
+        assert_eq!(request.get("api_key"), Some("secret"));
+        assert_eq!(request.get("app_id"), Some("app"));
+    }
+
+    #[test]
+    fn refuses_updates_without_id() {
+        let server = MockServer::start();
+        remote_config(&server);
+        let client = ApiClient::new(&Config::new("key", server.url()), "secret", "app");
+
+        let parameter = Parameter::new("color", json!("red"));
+        assert!(matches!(client.update_parameter(&parameter), Err(ApiError::Invalid(_))));
+        let condition = Condition::new("Austria", json!({}));
+        assert!(matches!(client.update_condition(&condition), Err(ApiError::Invalid(_))));
+        assert!(server.requests().is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! Clients for the server-side APIs of Countly, to query analytics data and set up apps from Rust.
+//!
+//! Unlike the tracking part of this crate, these calls are authenticated with the API key of a dashboard user (found in
+//! the account settings) and block the calling thread until the server has answered.
+
+mod management;
+pub use management::*;
+mod read;
+pub use read::*;
+
+use std::fmt;
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+use ureq::Agent;
+use crate::Config;
+
+/// Everything that can go wrong when calling the API.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The server answered with an error status, with the message it returned.
+    Status(u16, String),
+    /// The server couldn't be reached.
+    Transport(String),
+    /// The response didn't have the expected format.
+    Decode(String),
+    /// The arguments are missing something the request needs, like the id of what to update.
+    Invalid(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Status(status, msg) => write!(f, "Countly API returned {}: {}", status, msg),
+            Self::Transport(msg) => write!(f, "Countly API not reachable: {}", msg),
+            Self::Decode(msg) => write!(f, "Unexpected response from the Countly API: {}", msg),
+            Self::Invalid(msg) => write!(f, "Invalid Countly API request: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<ureq::Error> for ApiError {
+    fn from(err: ureq::Error) -> Self {
+        Self::Transport(err.to_string())
+    }
+}
+
+/// Client for the Countly API of a single app.
+///
+/// ```no_run
+/// use countly::{Config, api::{ApiClient, Period}};
+///
+/// let config = Config::new("APP_KEY", "https://countly.example.com");
+/// let client = ApiClient::new(&config, "API_KEY", "APP_ID");
+/// for day in client.sessions(Period::Days(30))? {
+///     println!("{}-{}-{}: {} sessions", day.year, day.month, day.day, day.totals.sessions);
+/// }
+/// # Ok::<(), countly::api::ApiError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    url: String,
+    app_key: String,
+    api_key: String,
+    app_id: String,
+    agent: Agent,
+}
+
+impl ApiClient {
+    /// Uses the server url and app key of `config`. `app_id` is the id of the app shown in the dashboard's app
+    /// management, not its app key.
+    pub fn new(config: &Config, api_key: &str, app_id: &str) -> Self {
+        Self {
+            url: config.url.trim_end_matches('/').to_owned(),
+            app_key: config.app_key.clone(),
+            api_key: api_key.to_owned(),
+            app_id: app_id.to_owned(),
+            agent: Agent::config_builder().http_status_as_error(false).build().into(),
+        }
+    }
+
+    /// A client for another app on the same server, with the same API key.
+    pub fn for_app(&self, app_id: &str) -> Self {
+        Self { app_id: app_id.to_owned(), ..self.clone() }
+    }
+
+    /// The id of the app this client queries.
+    pub fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
+    /// Calls `path` with the API key, app id and `params`, decoding the JSON response.
+    fn get<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Result<T, ApiError> {
+        let request = self.agent.get(&format!("{}{}", self.url, path))
+            .query("api_key", &self.api_key)
+            .query("app_id", &self.app_id)
+            .query_pairs(params.iter().copied());
+        decode(request.call()?)
+    }
+
+    /// Posts `params` to `path` with the API key and app id, for calls that change something.
+    fn post<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Result<T, ApiError> {
+        let auth = [("api_key", self.api_key.as_str()), ("app_id", self.app_id.as_str())];
+        let request = self.agent.post(&format!("{}{}", self.url, path));
+        decode(request.send_form(auth.iter().chain(params).copied())?)
+    }
+
+    /// Like [ApiClient::get], but authenticated with the app key like the SDKs instead of an API key.
+    fn get_sdk<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Result<T, ApiError> {
+        let request = self.agent.get(&format!("{}{}", self.url, path))
+            .query("app_key", &self.app_key)
+            .query_pairs(params.iter().copied());
+        decode(request.call()?)
+    }
+}
+
+fn decode<T: DeserializeOwned>(mut response: ureq::http::Response<ureq::Body>) -> Result<T, ApiError> {
+    let status = response.status().as_u16();
+    let body = response.body_mut().read_to_string()?;
+    if status >= 400 {
+        // Errors come as `{"result": "message"}`.
+        let msg = serde_json::from_str::<Json>(&body).ok()
+            .and_then(|json| json.get("result").and_then(Json::as_str).map(str::to_owned))
+            .unwrap_or(body);
+        return Err(ApiError::Status(status, msg));
+    }
+    serde_json::from_str(&body).map_err(|err| ApiError::Decode(err.to_string()))
+}
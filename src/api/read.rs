@@ -0,0 +1,405 @@
+//! The read API (`/o`), for the data shown in the dashboard.
+
+use std::collections::HashMap;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value as Json};
+use super::{ApiClient, ApiError};
+
+/// Page size used when iterating over [Pages] unless changed with [Pages::page_size].
+const DEFAULT_PAGE_SIZE: u64 = 100;
+
+/// Time range of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// The current hour.
+    Hour,
+    /// Today.
+    Day,
+    Yesterday,
+    /// The last number of days, including today.
+    Days(u32),
+    /// The current month.
+    Month,
+    /// From and to, in milliseconds since the epoch.
+    Range(u64, u64),
+}
+
+impl Period {
+    fn param(&self) -> String {
+        match self {
+            Self::Hour => "hour".to_owned(),
+            Self::Day => "day".to_owned(),
+            Self::Yesterday => "yesterday".to_owned(),
+            Self::Days(days) => format!("{}days", days),
+            Self::Month => "month".to_owned(),
+            Self::Range(from, to) => format!("[{},{}]", from, to),
+        }
+    }
+}
+
+/// Totals of a single day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Day<T> {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub totals: T,
+}
+
+/// Totals of an event, see [ApiClient::event_data].
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct EventTotals {
+    #[serde(rename = "c", default)]
+    pub count: f64,
+    #[serde(rename = "s", default)]
+    pub sum: f64,
+    #[serde(default)]
+    pub dur: f64,
+}
+
+/// Totals of sessions, see [ApiClient::sessions].
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct SessionTotals {
+    #[serde(rename = "t", default)]
+    pub sessions: f64,
+    #[serde(rename = "u", default)]
+    pub users: f64,
+    #[serde(rename = "n", default)]
+    pub new_users: f64,
+    /// Total duration of all sessions, in seconds.
+    #[serde(rename = "d", default)]
+    pub duration: f64,
+    #[serde(rename = "e", default)]
+    pub events: f64,
+}
+
+/// The custom events recorded for the app, see [ApiClient::events].
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EventList {
+    /// Event keys.
+    #[serde(default)]
+    pub list: Vec<String>,
+    /// Segmentation keys of each event.
+    #[serde(default)]
+    pub segments: HashMap<String, Vec<String>>,
+}
+
+/// A user profile, see [ApiClient::users].
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserProfile {
+    /// Internal user id.
+    pub uid: String,
+    /// Device id the user was last seen with.
+    #[serde(rename = "did")]
+    pub device_id: String,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    #[serde(rename = "cc")]
+    pub country_code: Option<String>,
+    #[serde(rename = "cty")]
+    pub city: Option<String>,
+    /// First seen, in seconds since the epoch.
+    #[serde(rename = "fs")]
+    pub first_seen: Option<u64>,
+    /// Last seen, in seconds since the epoch.
+    #[serde(rename = "ls")]
+    pub last_seen: Option<u64>,
+    #[serde(rename = "sc", default)]
+    pub session_count: u64,
+    #[serde(default)]
+    pub custom: Map<String, Json>,
+    /// Everything else stored for the user, like metrics.
+    #[serde(flatten)]
+    pub other: Map<String, Json>,
+}
+
+/// A group of crashes with the same stack trace, see [ApiClient::crash_groups].
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrashGroup {
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// The stack trace.
+    #[serde(default)]
+    pub error: String,
+    /// Number of reports.
+    #[serde(default)]
+    pub reports: u64,
+    /// Number of affected users.
+    #[serde(default)]
+    pub users: u64,
+    #[serde(default)]
+    pub is_resolved: bool,
+    #[serde(default)]
+    pub nonfatal: bool,
+    /// Last report, in seconds since the epoch.
+    #[serde(rename = "lastTs")]
+    pub last_seen: Option<u64>,
+    pub latest_version: Option<String>,
+}
+
+/// One page of a list.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items in the whole list.
+    pub total: u64,
+    /// Index of the first item in the whole list.
+    pub skip: u64,
+}
+
+impl<T> Page<T> {
+    /// Whether there are items after this page.
+    pub fn has_more(&self) -> bool {
+        self.skip + (self.items.len() as u64) < self.total
+    }
+}
+
+/// Iterates over all items of a list, fetching one page at a time as needed.
+pub struct Pages<'a, T> {
+    fetch: Box<dyn Fn(u64, u64) -> Result<Page<T>, ApiError> + 'a>,
+    page_size: u64,
+    skip: u64,
+    buffer: std::vec::IntoIter<T>,
+    done: bool,
+}
+
+impl<'a, T> Pages<'a, T> {
+    fn new(fetch: impl Fn(u64, u64) -> Result<Page<T>, ApiError> + 'a) -> Self {
+        Self { fetch: Box::new(fetch), page_size: DEFAULT_PAGE_SIZE, skip: 0, buffer: Vec::new().into_iter(), done: false }
+    }
+
+    /// Number of items to request at once (default: 100).
+    pub fn page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+}
+
+impl<'a, T> Iterator for Pages<'a, T> {
+    type Item = Result<T, ApiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.buffer.next() {
+            return Some(Ok(item));
+        }
+        if self.done {
+            return None;
+        }
+        match (self.fetch)(self.skip, self.page_size) {
+            Ok(page) => {
+                self.skip += page.items.len() as u64;
+                self.done = !page.has_more() || page.items.is_empty();
+                self.buffer = page.items.into_iter();
+                self.buffer.next().map(Ok)
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// `/o/db` answer.
+#[derive(Deserialize)]
+struct DbPage<T> {
+    #[serde(default)]
+    total: u64,
+    collections: Vec<T>,
+}
+
+/// Answer in the format of DataTables, used by most lists in the dashboard.
+#[derive(Deserialize)]
+struct TablePage<T> {
+    #[serde(rename = "iTotalDisplayRecords", default)]
+    total: u64,
+    #[serde(rename = "aaData")]
+    data: Vec<T>,
+}
+
+/// Turns the time series stored by the server (`{"2024": {"1": {"15": {"c": 3}}}}`, with totals of the year and month
+/// next to the nested values) into a list of days.
+fn days<T: DeserializeOwned>(data: &Json) -> Result<Vec<Day<T>>, ApiError> {
+    /// Nested values have numeric keys.
+    fn numbered(object: &Json) -> impl Iterator<Item = (u32, &Json)> {
+        object.as_object().into_iter()
+            .flat_map(|object| object.iter())
+            .filter_map(|(key, value)| key.parse().ok().map(|key| (key, value)))
+    }
+
+    let mut days = Vec::new();
+    for (year, months) in numbered(data) {
+        for (month, month_days) in numbered(months) {
+            for (day, totals) in numbered(month_days) {
+                let totals: Map<String, Json> = totals.as_object().into_iter()
+                    .flat_map(|totals| totals.iter())
+                    .filter(|(key, value)| key.parse::<u32>().is_err() && !value.is_object())
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                let totals = serde_json::from_value(Json::Object(totals)).map_err(|err| ApiError::Decode(err.to_string()))?;
+                days.push(Day { year: year as i32, month, day, totals });
+            }
+        }
+    }
+    days.sort_by_key(|day| (day.year, day.month, day.day));
+    Ok(days)
+}
+
+impl ApiClient {
+    /// All custom events recorded for the app and their segments.
+    pub fn events(&self) -> Result<EventList, ApiError> {
+        self.get("/o", &[("method", "get_events")])
+    }
+
+    /// Daily totals of the event `key`.
+    pub fn event_data(&self, key: &str, period: Period) -> Result<Vec<Day<EventTotals>>, ApiError> {
+        let data: Json = self.get("/o", &[("method", "events"), ("event", key), ("period", &period.param())])?;
+        days(&data)
+    }
+
+    /// Daily totals of sessions and users.
+    pub fn sessions(&self, period: Period) -> Result<Vec<Day<SessionTotals>>, ApiError> {
+        let data: Json = self.get("/o", &[("method", "sessions"), ("period", &period.param())])?;
+        days(&data)
+    }
+
+    /// All user profiles matching `filter` (a MongoDB query, like `{"custom.plan": "pro"}`), or all users.
+    ///
+    /// Read through the database viewer (`/o/db`), so the API key has to belong to a global admin.
+    pub fn users(&self, filter: Option<Json>) -> Pages<'_, UserProfile> {
+        Pages::new(move |skip, limit| self.users_page(filter.as_ref(), skip, limit))
+    }
+
+    /// A single page of [ApiClient::users], with the same permissions needed.
+    pub fn users_page(&self, filter: Option<&Json>, skip: u64, limit: u64) -> Result<Page<UserProfile>, ApiError> {
+        let collection = format!("app_users{}", self.app_id);
+        let filter = filter.map(Json::to_string).unwrap_or_else(|| "{}".to_owned());
+        let page: DbPage<UserProfile> = self.get("/o/db", &[
+            ("dbs", "countly"),
+            ("collection", &collection),
+            ("filter", &filter),
+            ("skip", &skip.to_string()),
+            ("limit", &limit.to_string()),
+        ])?;
+        Ok(Page { items: page.collections, total: page.total, skip })
+    }
+
+    /// All crash groups, in the order the server keeps them.
+    pub fn crash_groups(&self) -> Pages<'_, CrashGroup> {
+        Pages::new(move |skip, limit| self.crash_groups_page(skip, limit))
+    }
+
+    /// A single page of [ApiClient::crash_groups].
+    pub fn crash_groups_page(&self, skip: u64, limit: u64) -> Result<Page<CrashGroup>, ApiError> {
+        let page: TablePage<CrashGroup> = self.get("/o", &[
+            ("method", "crashes"),
+            ("iDisplayStart", &skip.to_string()),
+            ("iDisplayLength", &limit.to_string()),
+        ])?;
+        Ok(Page { items: page.data, total: page.total, skip })
+    }
+
+    /// Remote config values for `device_id`, like the SDKs get them. Only `keys` are fetched if not empty.
+    pub fn remote_config(&self, device_id: &str, keys: &[&str]) -> Result<Map<String, Json>, ApiError> {
+        let keys = serde_json::to_string(keys).unwrap_or_default();
+        let mut params = vec![("method", "fetch_remote_config"), ("device_id", device_id)];
+        if keys != "[]" {
+            params.push(("keys", &keys));
+        }
+        self.get_sdk("/o/sdk", &params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use countly_mock::MockServer;
+    use serde_json::json;
+    use crate::Config;
+    use super::*;
+
+    #[test]
+    fn flattens_time_series() {
+        let data = json!({
+            "2024": {
+                "c": 7,
+                "12": { "c": 7, "31": { "c": 2, "s": 1.5 } },
+                "1": { "c": 5, "15": { "c": 5, "dur": 30, "0": { "c": 1 } } },
+            },
+            "meta": { "segments": [] },
+        });
+        let days: Vec<Day<EventTotals>> = days(&data).unwrap();
+        assert_eq!(days, vec![
+            Day { year: 2024, month: 1, day: 15, totals: EventTotals { count: 5.0, sum: 0.0, dur: 30.0 } },
+            Day { year: 2024, month: 12, day: 31, totals: EventTotals { count: 2.0, sum: 1.5, dur: 0.0 } },
+        ]);
+    }
+
+    #[test]
+    fn fetches_remote_config() {
+        let server = MockServer::start();
+        let mut values = Map::new();
+        values.insert("color".to_owned(), json!("red"));
+        values.insert("limit".to_owned(), json!(5));
+        server.set_remote_config(values);
+        let client = ApiClient::new(&Config::new("key", server.url()), "secret", "app");
+
+        let config = client.remote_config("device", &["limit"]).unwrap();
+        assert_eq!(Json::Object(config), json!({ "limit": 5 }));
+        let request = &server.requests()[0];
+        assert_eq!(request.app_key(), "key");
+        assert_eq!(request.device_id(), "device");
+        assert_eq!(request.get("method"), Some("fetch_remote_config"));
+
+        server.fail_all(Some(500));
+        assert!(matches!(client.remote_config("device", &[]), Err(ApiError::Status(500, _))));
+    }
+
+    /// Answers like the server with `total` numbered items, the requested slice of them wrapped by `page`.
+    fn paginate(server: &MockServer, path: &str, total: u64, skip: &'static str, limit: &'static str, page: fn(Vec<Json>) -> Json) {
+        server.route(path, move |request| {
+            let skip: u64 = request.get(skip).unwrap().parse().unwrap();
+            let limit: u64 = request.get(limit).unwrap().parse().unwrap();
+            page((skip..total.min(skip + limit)).map(|idx| json!(idx)).collect())
+        });
+    }
+
+    #[test]
+    fn pages_through_lists() {
+        let server = MockServer::start();
+        let client = ApiClient::new(&Config::new("key", server.url()), "secret", "app");
+        paginate(&server, "/o/db", 5, "skip", "limit", |items| {
+            let users: Vec<Json> = items.iter().map(|idx| json!({ "uid": idx.to_string(), "did": format!("device{}", idx) })).collect();
+            json!({ "total": 5, "collections": users })
+        });
+        paginate(&server, "/o", 3, "iDisplayStart", "iDisplayLength", |items| {
+            let groups: Vec<Json> = items.iter().map(|idx| json!({ "_id": idx.to_string() })).collect();
+            json!({ "iTotalDisplayRecords": 3, "aaData": groups })
+        });
+
+        let users: Vec<String> = client.users(Some(json!({ "custom.plan": "pro" }))).page_size(2)
+            .map(|user| user.unwrap().device_id)
+            .collect();
+        assert_eq!(users, ["device0", "device1", "device2", "device3", "device4"]);
+        let requests = server.requests();
+        let skips: Vec<&str> = requests.iter().map(|request| request.get("skip").unwrap()).collect();
+        assert_eq!(skips, ["0", "2", "4"]);
+        assert_eq!(requests[0].get("collection"), Some("app_usersapp"));
+        assert_eq!(requests[0].get("filter"), Some(r#"{"custom.plan":"pro"}"#));
+        assert_eq!(requests[0].get("api_key"), Some("secret"));
+
+        server.reset();
+        let groups: Vec<String> = client.crash_groups().page_size(2).map(|group| group.unwrap().id).collect();
+        assert_eq!(groups, ["0", "1", "2"]);
+        assert_eq!(server.requests().len(), 2);
+
+        server.reset();
+        server.fail_next(1, 401);
+        let mut failing = client.crash_groups();
+        assert!(matches!(failing.next(), Some(Err(ApiError::Status(401, _)))));
+        assert!(failing.next().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! Tracking the views of a single-page app from its navigations, see
+//! [Countly::enable_auto_view_tracking](crate::Countly::enable_auto_view_tracking).
+// Only the browser backends start it.
+#![cfg_attr(any(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))), all(feature = "node", not(feature = "pure"))), allow(dead_code))]
+
+use std::{cell::RefCell, rc::Rc};
+use js_sys::{Function, Reflect};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+
+/// The methods of `History` that navigate without an event.
+const HOOKED: [&str; 2] = ["pushState", "replaceState"];
+/// The events of the window that are navigations.
+const EVENTS: [&str; 2] = ["popstate", "hashchange"];
+
+pub(crate) type ViewName = Box<dyn FnMut(&str) -> Option<String>>;
+type Hook = Closure<dyn FnMut(JsValue, JsValue, JsValue) -> Result<JsValue, JsValue>>;
+type Listener = Closure<dyn FnMut(web_sys::Event)>;
+
+struct Views {
+    view_name: ViewName,
+    /// The name of the view tracked last.
+    last: Option<String>,
+    /// The timeout that tracks the current view, a redirect right after a navigation only counts once.
+    pending: Option<i32>,
+    stopped: bool,
+}
+
+impl Views {
+    /// The name of the view at `url`, unless it's the view tracked last or shouldn't be tracked.
+    fn next(&mut self, url: &str) -> Option<String> {
+        let name = (self.view_name)(url)?;
+        if self.last.as_deref() == Some(name.as_str()) {
+            return None;
+        }
+        self.last = Some(name.clone());
+        Some(name)
+    }
+}
+
+/// The path of the page with query and fragment, like `/users/12?tab=orders#top`.
+fn relative_url(location: &web_sys::Location) -> String {
+    [location.pathname(), location.search(), location.hash()]
+        .iter()
+        .filter_map(|part| part.as_ref().ok())
+        .map(String::as_str)
+        .collect()
+}
+
+/// Tracks the view of the current page, if it changed.
+fn visit(views: &RefCell<Views>, window: &web_sys::Window, track: fn(&str)) {
+    let url = relative_url(&window.location());
+    let name = views.borrow_mut().next(&url);
+    if let Some(name) = name {
+        track(&name);
+    }
+}
+
+/// Hooks the navigations of the window and tracks a view for each of them, until it's dropped.
+#[must_use = "views are only tracked while this is alive"]
+pub struct AutoViewTracking {
+    window: web_sys::Window,
+    views: Rc<RefCell<Views>>,
+    /// Kept alive while a timeout may call it.
+    _timer: Closure<dyn FnMut()>,
+    /// The replaced methods of `History` with what replaced them.
+    hooks: Vec<(&'static str, Function, Hook)>,
+    listeners: Vec<(&'static str, Listener)>,
+}
+
+impl AutoViewTracking {
+    /// Tracks the current view right away with `track`, then every navigation. `None` without a window.
+    pub(crate) fn start(view_name: ViewName, track: fn(&str)) -> Option<Self> {
+        let window = web_sys::window()?;
+        let history = window.history().ok()?;
+        let views = Rc::new(RefCell::new(Views { view_name, last: None, pending: None, stopped: false }));
+
+        let timer = {
+            let views = views.clone();
+            let window = window.clone();
+            Closure::wrap(Box::new(move || {
+                views.borrow_mut().pending = None;
+                visit(&views, &window, track);
+            }) as Box<dyn FnMut()>)
+        };
+        let schedule = {
+            let views = views.clone();
+            let window = window.clone();
+            let timer: Function = timer.as_ref().unchecked_ref::<Function>().clone();
+            Rc::new(move || {
+                let mut views = views.borrow_mut();
+                if views.stopped || views.pending.is_some() {
+                    return;
+                }
+                views.pending = window.set_timeout_with_callback_and_timeout_and_arguments_0(&timer, 0).ok();
+            })
+        };
+
+        let mut hooks = Vec::new();
+        for method in HOOKED.iter().copied() {
+            let original = match Reflect::get(&history, &JsValue::from_str(method)).ok().and_then(|original| original.dyn_into::<Function>().ok()) {
+                Some(original) => original,
+                None => continue,
+            };
+            let hook: Hook = {
+                let history = history.clone();
+                let original = original.clone();
+                let schedule = schedule.clone();
+                Closure::wrap(Box::new(move |state: JsValue, title: JsValue, url: JsValue| {
+                    let result = original.call3(&history, &state, &title, &url)?;
+                    schedule();
+                    Ok(result)
+                }) as Box<dyn FnMut(JsValue, JsValue, JsValue) -> Result<JsValue, JsValue>>)
+            };
+            if Reflect::set(&history, &JsValue::from_str(method), hook.as_ref()).unwrap_or(false) {
+                hooks.push((method, original, hook));
+            }
+        }
+
+        let listeners = EVENTS.iter().copied().map(|event| {
+            let schedule = schedule.clone();
+            let listener = Closure::wrap(Box::new(move |_: web_sys::Event| schedule()) as Box<dyn FnMut(web_sys::Event)>) as Listener;
+            let _ = window.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+            (event, listener)
+        }).collect();
+
+        visit(&views, &window, track);
+        Some(Self { window, views, _timer: timer, hooks, listeners })
+    }
+
+    /// Stops tracking views. Same as dropping it.
+    pub fn stop(self) {}
+}
+
+impl Drop for AutoViewTracking {
+    fn drop(&mut self) {
+        let pending = {
+            let mut views = self.views.borrow_mut();
+            views.stopped = true;
+            views.pending.take()
+        };
+        // The timer closure is only dropped after this, when it can't fire anymore.
+        if let Some(pending) = pending {
+            self.window.clear_timeout_with_handle(pending);
+        }
+        for (event, listener) in &self.listeners {
+            let _ = self.window.remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        }
+        if let Ok(history) = self.window.history() {
+            for (method, original, hook) in self.hooks.drain(..) {
+                let key = JsValue::from_str(method);
+                let current = Reflect::get(&history, &key).unwrap_or(JsValue::UNDEFINED);
+                if current == *hook.as_ref() {
+                    let _ = Reflect::set(&history, &key, &original);
+                } else {
+                    // Someone else wrapped the method since and still calls the hook, which only forwards from now on.
+                    hook.forget();
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for AutoViewTracking {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AutoViewTracking").field("last", &self.views.borrow().last).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_duplicate_views() {
+        let mut views = Views {
+            view_name: Box::new(|url| {
+                let path = url.split(['?', '#']).next().unwrap_or_default();
+                if path.starts_with("/admin") {
+                    None
+                } else {
+                    Some(path.to_owned())
+                }
+            }),
+            last: None,
+            pending: None,
+            stopped: false,
+        };
+        assert_eq!(views.next("/users").as_deref(), Some("/users"));
+        assert_eq!(views.next("/users?page=2"), None);
+        assert_eq!(views.next("/users#top"), None);
+        assert_eq!(views.next("/admin"), None);
+        assert_eq!(views.next("/users/12").as_deref(), Some("/users/12"));
+        assert_eq!(views.next("/users").as_deref(), Some("/users"));
+    }
+}
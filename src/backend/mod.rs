@@ -0,0 +1,74 @@
+//! The implementations behind [Countly](crate::Countly). Exactly one of them is active, depending on the enabled features.
+//! With both `pure` and `native`, `pure` is used for wasm and `native` for everything else.
+
+use std::collections::HashMap;
+use wasm_bindgen::JsValue;
+use crate::{
+    Config,
+    auto_views::{AutoViewTracking, ViewName},
+    countly::{CustomEvent, Unsupported, UserDataOp},
+    device_id::{DeviceIdChange, DeviceIdType},
+    gdpr::ConsentFeatures,
+    location::Location,
+    user_details::UserDetails,
+};
+
+#[cfg(not(any(feature = "pure", feature = "native", feature = "node")))]
+mod web;
+#[cfg(not(any(feature = "pure", feature = "native", feature = "node")))]
+pub(crate) use web::WebSdk as Active;
+
+#[cfg(all(feature = "pure", any(target_arch = "wasm32", not(feature = "native"))))]
+mod pure;
+#[cfg(all(feature = "pure", any(target_arch = "wasm32", not(feature = "native"))))]
+pub(crate) use pure::Pure as Active;
+
+#[cfg(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))))]
+mod native;
+#[cfg(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))))]
+pub(crate) use native::Native as Active;
+
+#[cfg(all(feature = "node", not(any(feature = "pure", feature = "native"))))]
+mod node;
+#[cfg(all(feature = "node", not(any(feature = "pure", feature = "native"))))]
+pub(crate) use node::NodeSdk as Active;
+
+/// Everything [Countly](crate::Countly) forwards to. See there for the documentation of the individual functions.
+pub(crate) trait Backend {
+    fn configure(config: Config);
+    fn enable_session_tracking();
+    fn track_pageview(name: Option<&str>, filter: Option<&[&str]>);
+    /// `None` goes back to the default view name, or URL below.
+    fn set_view_name_callback(callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported>;
+    fn set_view_url_callback(callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported>;
+    fn enable_auto_view_tracking(view_name: ViewName) -> Result<AutoViewTracking, Unsupported>;
+    fn enable_link_tracking(parent: Option<web_sys::Element>) -> Result<(), Unsupported>;
+    fn enable_form_submission_tracking(parent: Option<&web_sys::Element>, include_hidden: bool) -> Result<(), Unsupported>;
+    fn enable_conversion_reporting(name: Option<&str>);
+    fn opt_in();
+    fn opt_out();
+    fn enable_form_data_collection(parent: Option<&web_sys::Element>, custom_properties: bool) -> Result<(), Unsupported>;
+    fn collect_from_facebook(custom_properties: &HashMap<String, String>) -> Result<(), Unsupported>;
+    fn add_event(event: CustomEvent);
+    fn start_event(name: &str);
+    fn end_event(name: &str);
+    fn set_user_details(details: UserDetails);
+    fn user_data(key: &str, op: UserDataOp);
+    fn user_data_save();
+    fn enable_track_errors(segments: Option<HashMap<String, String>>);
+    fn log_error(error: JsValue, segments: Option<HashMap<String, String>>);
+    fn add_log(msg: &str);
+    fn device_id() -> Option<String>;
+    fn device_id_type() -> Option<DeviceIdType>;
+    fn change_device_id(id: &str, change: DeviceIdChange);
+    fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>);
+    fn add_consent(features: &[&str]);
+    fn remove_consent(features: &[&str]);
+    fn begin_session(no_heart_beat: bool);
+    fn extend_session(secs: f64);
+    fn end_session(secs: Option<f64>);
+    fn enable_offline_mode();
+    fn disable_offline_mode(device_id: Option<&str>);
+    fn set_location(location: Location);
+    fn disable_location();
+}
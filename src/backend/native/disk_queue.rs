@@ -0,0 +1,216 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use serde::{Deserialize, Serialize};
+use crate::protocol::{queue::Persist, request::Request};
+
+/// How many superfluous records the file may contain before it is rewritten.
+const COMPACT_SLACK: usize = 64;
+
+/// One line in the queue file.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    /// A request was appended.
+    Push(Request),
+    /// This many requests were removed from the front.
+    Pop(usize),
+}
+
+/// Keeps the request queue in an append-only file of JSON lines, so every change costs a single small write.
+///
+/// The file is replayed on startup and compacted whenever it contains too many records of requests that are gone
+/// already. A line that was only partially written because of a crash is skipped.
+///
+/// I/O errors are ignored, the queue keeps working in memory and the file catches up on the next compaction.
+pub(super) struct DiskQueue {
+    path: PathBuf,
+    file: Option<File>,
+    /// Number of records in the file.
+    records: usize,
+}
+
+impl DiskQueue {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, file: None, records: 0 }
+    }
+
+    fn replay(path: &Path) -> io::Result<VecDeque<Request>> {
+        let mut queue = VecDeque::new();
+        // Bytes, a line torn by a crash can end in the middle of a UTF-8 sequence.
+        for line in BufReader::new(File::open(path)?).split(b'\n') {
+            match serde_json::from_slice(&line?) {
+                Ok(Record::Push(request)) => queue.push_back(request),
+                Ok(Record::Pop(count)) => {
+                    queue.drain(..count.min(queue.len()));
+                }
+                Err(_) => continue,
+            }
+        }
+        Ok(queue)
+    }
+
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        if self.file.is_none() {
+            self.file = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        file.sync_data()?;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn rewrite(&mut self, queue: &VecDeque<Request>) -> io::Result<()> {
+        self.file = None;
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            for request in queue {
+                let mut line = serde_json::to_vec(&Record::Push(request.clone()))?;
+                line.push(b'\n');
+                file.write_all(&line)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        self.records = queue.len();
+        Ok(())
+    }
+
+    fn compact_if_needed(&mut self, queue: &VecDeque<Request>) {
+        if self.records > queue.len() * 2 + COMPACT_SLACK {
+            self.store(queue);
+        }
+    }
+}
+
+impl Persist for DiskQueue {
+    fn load(&mut self) -> Vec<Request> {
+        let queue = Self::replay(&self.path).unwrap_or_default();
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = self.rewrite(&queue);
+        queue.into()
+    }
+
+    fn store(&mut self, queue: &VecDeque<Request>) {
+        let _ = self.rewrite(queue);
+    }
+
+    fn appended(&mut self, queue: &VecDeque<Request>, request: &Request) {
+        if self.append(&Record::Push(request.clone())).is_err() {
+            self.store(queue);
+        }
+        self.compact_if_needed(queue);
+    }
+
+    fn removed_front(&mut self, queue: &VecDeque<Request>, count: usize) {
+        if self.append(&Record::Pop(count)).is_err() {
+            self.store(queue);
+        }
+        self.compact_if_needed(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{Now, queue::Queue};
+    use super::*;
+
+    fn request(id: u64) -> Request {
+        Request::new("key", "device", Now::utc(id))
+    }
+
+    fn timestamps(queue: &Queue<DiskQueue>) -> Vec<u64> {
+        queue.iter().map(|request| request.get("timestamp").unwrap().parse().unwrap()).collect()
+    }
+
+    /// A fresh file in the temp directory, removed when the test ends.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("countly-disk-queue-{}-{}.queue", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+
+        fn lines(&self) -> usize {
+            fs::read(&self.0).unwrap().split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).count()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn replays_after_restart() {
+        let file = TempFile::new("replay");
+        let mut queue = Queue::new(None, DiskQueue::new(file.0.clone()));
+        (1..=4).for_each(|id| queue.push(request(id)));
+        queue.pop_front();
+        drop(queue);
+
+        let queue = Queue::new(None, DiskQueue::new(file.0.clone()));
+        assert_eq!(timestamps(&queue), [2, 3, 4]);
+        // Loading compacts the file.
+        assert_eq!(file.lines(), 3);
+    }
+
+    #[test]
+    fn skips_torn_lines() {
+        let file = TempFile::new("torn");
+        let mut queue = Queue::new(None, DiskQueue::new(file.0.clone()));
+        queue.push(request(1));
+        queue.push(request(2));
+        drop(queue);
+        let mut torn = OpenOptions::new().append(true).open(&file.0).unwrap();
+        torn.write_all(b"{\"push\":{\"params\":[[\"text\",\"caf\xc3").unwrap();
+        drop(torn);
+
+        let mut queue = Queue::new(None, DiskQueue::new(file.0.clone()));
+        assert_eq!(timestamps(&queue), [1, 2]);
+        queue.push(request(3));
+        drop(queue);
+        assert_eq!(timestamps(&Queue::new(None, DiskQueue::new(file.0.clone()))), [1, 2, 3]);
+    }
+
+    #[test]
+    fn compacts_removed_requests() {
+        let file = TempFile::new("compact");
+        let mut queue = Queue::new(None, DiskQueue::new(file.0.clone()));
+        for id in 0..100 {
+            queue.push(request(id));
+            queue.pop_front();
+        }
+        assert!(file.lines() <= COMPACT_SLACK + 1, "{} records left", file.lines());
+        queue.push(request(100));
+        drop(queue);
+        assert_eq!(timestamps(&Queue::new(None, DiskQueue::new(file.0.clone()))), [100]);
+    }
+
+    #[test]
+    fn keeps_the_newest_requests() {
+        let file = TempFile::new("limit");
+        let mut queue = Queue::new(Some(3), DiskQueue::new(file.0.clone()));
+        (1..=5).for_each(|id| queue.push(request(id)));
+        assert_eq!(timestamps(&queue), [3, 4, 5]);
+        drop(queue);
+
+        assert_eq!(timestamps(&Queue::new(Some(3), DiskQueue::new(file.0.clone()))), [3, 4, 5]);
+        let queue = Queue::new(Some(2), DiskQueue::new(file.0.clone()));
+        assert_eq!(timestamps(&queue), [4, 5]);
+        drop(queue);
+        assert_eq!(file.lines(), 2);
+    }
+}
@@ -0,0 +1,571 @@
+//! Implementation of the Countly protocol for native targets, sending from a background thread.
+
+mod disk_queue;
+mod transport;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, atomic::{AtomicBool, Ordering}},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use serde_json::{Map, Value as Json};
+use wasm_bindgen::JsValue;
+use crate::{
+    Config,
+    auto_views::{AutoViewTracking, ViewName},
+    protocol::{Now, queue::Queue, request::{Request, Server}, scheduler::{Clock, Scheduler}, tracker::Tracker},
+    countly::{CustomEvent, Unsupported, UserDataOp},
+    device_id::{DeviceIdChange, DeviceIdGenerator, DeviceIdType, UuidV4},
+    gdpr::ConsentFeatures,
+    location::Location,
+    routes,
+    user_details::UserDetails,
+};
+use self::disk_queue::DiskQueue;
+use super::Backend;
+
+/// How often flushing checks whether everything has been sent.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct State {
+    tracker: Tracker<Option<DiskQueue>>,
+    scheduler: Scheduler,
+    server: Server,
+    /// Where the device id is stored, followed by its type on the second line, if there is a storage directory.
+    device_id_path: Option<PathBuf>,
+    /// Cleared to stop the sender thread belonging to this configuration.
+    running: Arc<AtomicBool>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<State>> {
+    // A panic while holding the lock doesn't leave the tracker in an inconsistent state worth giving up on.
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Runs `f` on the tracker, if [Countly::configure](crate::Countly::configure) has been called.
+fn with<R>(f: impl FnOnce(&mut State, Now) -> R) -> Option<R> {
+    lock().as_mut().map(|state| f(state, now()))
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Now {
+        now()
+    }
+}
+
+/// Current time. Hour and day of week are reported in UTC, since the standard library can't tell the local time zone.
+fn now() -> Now {
+    Now::utc(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+}
+
+fn debug_log(config: &Config, msg: &str) {
+    if config.debug {
+        eprintln!("[Countly] {}", msg);
+    }
+}
+
+fn metrics(config: &Config) -> Map<String, Json> {
+    let mut metrics = Map::new();
+    metrics.insert("_os".to_owned(), Json::from(std::env::consts::OS));
+    if let Some(app_version) = &config.app_version {
+        metrics.insert("_app_version".to_owned(), Json::from(app_version.as_str()));
+    }
+    metrics
+}
+
+/// The temporary id of offline mode is not stored, the previous one is restored after a restart.
+fn store_device_id(state: &State) {
+    if let Some(path) = &state.device_id_path {
+        let device_id_type = state.tracker.device_id_type();
+        if device_id_type != DeviceIdType::Temporary {
+            let _ = fs::write(path, format!("{}\n{}", state.tracker.device_id(), device_id_type.code()));
+        }
+    }
+}
+
+/// Files written before the type was stored only contain the id, which was generated unless it was configured.
+fn load_device_id(path: &PathBuf) -> Option<(String, DeviceIdType)> {
+    let stored = fs::read_to_string(path).ok()?;
+    let mut lines = stored.lines();
+    let id = lines.next().map(str::trim).filter(|id| !id.is_empty())?;
+    let device_id_type = lines.next()
+        .and_then(|code| code.trim().parse().ok())
+        .and_then(DeviceIdType::from_code)
+        .unwrap_or(DeviceIdType::SdkGenerated);
+    Some((id.to_owned(), device_id_type))
+}
+
+/// Returns the next request to send, if one is due.
+fn next_request(now: Now) -> Option<(Request, Server)> {
+    lock().as_mut().and_then(|state| {
+        let request = state.scheduler.poll(&mut state.tracker, now)?;
+        Some((request, state.server.clone()))
+    })
+}
+
+/// Reports the result of sending a request returned by [next_request].
+fn complete(request: &Request, result: Result<(), String>, now: Now) {
+    if let Some(state) = lock().as_mut() {
+        if let Err(err) = &result {
+            debug_log(&state.tracker.config, &format!("Request failed: {}", err));
+        }
+        state.scheduler.complete(&mut state.tracker, request, result.is_ok(), now);
+    }
+}
+
+/// Milliseconds the sender can sleep before anything is due.
+fn idle_time(now: Now) -> u64 {
+    lock().as_ref().map(|state| state.scheduler.wait(now)).unwrap_or(0).max(1)
+}
+
+/// Sends the queue to the server until [State::running] is cleared.
+fn run(running: Arc<AtomicBool>, clock: impl Clock) {
+    while running.load(Ordering::SeqCst) {
+        let now = clock.now();
+        match next_request(now) {
+            Some((request, server)) => {
+                let result = transport::send(&server, &request);
+                if !running.load(Ordering::SeqCst) {
+                    // Reconfigured in the meantime, the request is still in the queue and will be sent again.
+                    break;
+                }
+                complete(&request, result, clock.now());
+            }
+            None => thread::sleep(Duration::from_millis(idle_time(now))),
+        }
+    }
+}
+
+/// Like [run], as a task on the Tokio runtime. Requests are still sent by a blocking client, on the blocking thread pool.
+#[cfg(feature = "tokio")]
+async fn run_async(running: Arc<AtomicBool>, clock: impl Clock) {
+    while running.load(Ordering::SeqCst) {
+        let now = clock.now();
+        match next_request(now) {
+            Some((request, server)) => {
+                let sent = request.clone();
+                let result = tokio::task::spawn_blocking(move || transport::send(&server, &sent)).await
+                    .unwrap_or_else(|err| Err(err.to_string()));
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                complete(&request, result, clock.now());
+            }
+            None => tokio::time::sleep(Duration::from_millis(idle_time(now))).await,
+        }
+    }
+}
+
+/// Starts sending in the background, as a task if there is a Tokio runtime and in a thread otherwise.
+fn spawn_sender(running: Arc<AtomicBool>) {
+    #[cfg(feature = "tokio")]
+    {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(run_async(running, SystemClock));
+            return;
+        }
+    }
+    thread::spawn(move || run(running, SystemClock));
+}
+
+/// Whether nothing is waiting to be sent, moving recorded events into requests first.
+fn flushed() -> bool {
+    with(|state, now| {
+        state.tracker.flush_events(now);
+        state.tracker.queue.front().is_none() && !state.scheduler.is_sending()
+    }).unwrap_or(true)
+}
+
+/// Stops the sender after moving recorded events into the queue, keeping unsent requests on disk. Returns false
+/// without stopping while a request is on its way to the server. Checked under the same lock, so the sender can't
+/// start another request in between.
+fn try_stop() -> bool {
+    let mut guard = lock();
+    if guard.as_ref().map(|state| state.scheduler.is_sending()).unwrap_or(false) {
+        return false;
+    }
+    if let Some(mut state) = guard.take() {
+        state.tracker.flush_events(now());
+        state.running.store(false, Ordering::SeqCst);
+        debug_log(&state.tracker.config, "Shut down");
+    }
+    true
+}
+
+/// The native implementation, see the module documentation.
+pub(crate) struct Native;
+
+impl Native {
+    fn unsupported(feature: &str) {
+        with(|state, _| debug_log(&state.tracker.config, &format!("{} is not supported on native targets", feature)));
+    }
+
+    /// Waits until everything recorded so far has been sent.
+    pub(crate) fn flush(timeout: Duration) -> bool {
+        let deadline = std::time::Instant::now() + timeout;
+        while !flushed() {
+            if std::time::Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        true
+    }
+
+    /// Like [Native::flush], without blocking the runtime.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn flush_async() {
+        while !flushed() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Waits for a request that is being sent and stops sending.
+    pub(crate) fn shutdown() {
+        while !try_stop() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Like [Native::shutdown], without blocking the runtime.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn shutdown_async() {
+        while !try_stop() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Moves recorded events into requests and returns all unsent requests, encoded as they would be sent.
+    pub(crate) fn pending_requests() -> Vec<String> {
+        with(|state, now| {
+            state.tracker.flush_events(now);
+            state.tracker.queue.iter().map(|request| state.server.encode(request)).collect()
+        }).unwrap_or_default()
+    }
+}
+
+impl Backend for Native {
+    fn configure(config: Config) {
+        let prefix = config.namespace.clone().unwrap_or_else(|| config.app_key.clone());
+        let device_id_path = config.storage_dir.as_ref().map(|dir| dir.join(format!("{}.device_id", prefix)));
+        let (device_id, device_id_type) = device_id_path.as_ref()
+            .and_then(load_device_id)
+            .unwrap_or_else(|| {
+                let id = match &config.device_id_generator {
+                    Some(generator) => generator.generate(),
+                    None => UuidV4.generate(),
+                };
+                (id, DeviceIdType::SdkGenerated)
+            });
+        let persist = config.storage_dir.as_ref().map(|dir| DiskQueue::new(dir.join(format!("{}.queue", prefix))));
+        let queue = Queue::new(config.queue_size, persist);
+        let scheduler = Scheduler::new(&config, now().timestamp);
+        let server = Server::new(&config);
+        let metrics = metrics(&config);
+        let tracker = Tracker::new(config, queue, device_id, device_id_type, std::env::consts::OS, metrics);
+        let running = Arc::new(AtomicBool::new(true));
+        let state = State { tracker, scheduler, server, device_id_path, running: running.clone() };
+        store_device_id(&state);
+        debug_log(&state.tracker.config, "Initialized");
+
+        let previous = lock().replace(state);
+        if let Some(previous) = previous {
+            previous.running.store(false, Ordering::SeqCst);
+        }
+        spawn_sender(running);
+    }
+
+    fn enable_session_tracking() {
+        with(|state, now| state.tracker.begin_session(true, now));
+    }
+
+    fn track_pageview(name: Option<&str>, filter: Option<&[&str]>) {
+        let name = match name {
+            Some(name) => routes::normalize(name),
+            None => return Self::unsupported("Tracking a pageview without a name"),
+        };
+        // Filtered by route, like the other backends.
+        if filter.map(|filter| filter.contains(&name.as_str())).unwrap_or(false) {
+            return;
+        }
+        with(|state, now| state.tracker.track_view(&name, None, now));
+    }
+
+    fn set_view_name_callback(_callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Setting a view name callback", "native"))
+    }
+
+    fn set_view_url_callback(_callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Setting a view URL callback", "native"))
+    }
+
+    fn enable_auto_view_tracking(_view_name: ViewName) -> Result<AutoViewTracking, Unsupported> {
+        Err(Unsupported::new("Automatic view tracking", "native"))
+    }
+
+    fn enable_link_tracking(_parent: Option<web_sys::Element>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Link tracking", "native"))
+    }
+
+    fn enable_form_submission_tracking(_parent: Option<&web_sys::Element>, _include_hidden: bool) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Form submission tracking", "native"))
+    }
+
+    fn enable_conversion_reporting(name: Option<&str>) {
+        match name {
+            Some(name) => {
+                with(|state, now| state.tracker.report_conversion(name, now));
+            }
+            None => Self::unsupported("Reporting a conversion without a campaign id"),
+        }
+    }
+
+    fn opt_in() {
+        with(|state, _| state.tracker.set_ignored(false));
+    }
+
+    fn opt_out() {
+        with(|state, _| state.tracker.set_ignored(true));
+    }
+
+    fn enable_form_data_collection(_parent: Option<&web_sys::Element>, _custom_properties: bool) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from forms", "native"))
+    }
+
+    fn collect_from_facebook(_custom_properties: &HashMap<String, String>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from Facebook", "native"))
+    }
+
+    fn add_event(event: CustomEvent) {
+        with(|state, now| state.tracker.add_event(event, now));
+    }
+
+    fn start_event(name: &str) {
+        with(|state, now| state.tracker.start_event(name, now));
+    }
+
+    fn end_event(name: &str) {
+        with(|state, now| state.tracker.end_event(name, now));
+    }
+
+    fn set_user_details(details: UserDetails) {
+        with(|state, now| state.tracker.user_details(&details, now));
+    }
+
+    fn user_data(key: &str, op: UserDataOp) {
+        with(|state, _| state.tracker.user_data(key, op));
+    }
+
+    fn user_data_save() {
+        with(|state, now| state.tracker.user_data_save(now));
+    }
+
+    fn enable_track_errors(segments: Option<HashMap<String, String>>) {
+        with(|state, _| state.tracker.set_crash_segments(segments));
+    }
+
+    fn log_error(_error: JsValue, segments: Option<HashMap<String, String>>) {
+        // A JsValue can't be inspected outside of a JavaScript host.
+        with(|state, now| state.tracker.log_error("Error", true, segments, now));
+    }
+
+    fn add_log(msg: &str) {
+        with(|state, _| state.tracker.add_log(msg));
+    }
+
+    fn device_id() -> Option<String> {
+        with(|state, _| state.tracker.device_id().to_owned())
+    }
+
+    fn device_id_type() -> Option<DeviceIdType> {
+        with(|state, _| state.tracker.device_id_type())
+    }
+
+    fn change_device_id(id: &str, change: DeviceIdChange) {
+        with(|state, now| {
+            state.tracker.change_device_id(id, change, now);
+            store_device_id(state);
+        });
+    }
+
+    fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>) {
+        with(|state, _| state.tracker.group_features(groups));
+    }
+
+    fn add_consent(features: &[&str]) {
+        with(|state, now| state.tracker.add_consent(features, now));
+    }
+
+    fn remove_consent(features: &[&str]) {
+        with(|state, now| state.tracker.remove_consent(features, now));
+    }
+
+    fn begin_session(no_heart_beat: bool) {
+        with(|state, now| state.tracker.begin_session(!no_heart_beat, now));
+    }
+
+    fn extend_session(secs: f64) {
+        with(|state, now| state.tracker.extend_session(Some(secs), now));
+    }
+
+    fn end_session(secs: Option<f64>) {
+        with(|state, now| state.tracker.end_session(secs, now));
+    }
+
+    fn enable_offline_mode() {
+        with(|state, now| state.tracker.enable_offline_mode(now));
+    }
+
+    fn disable_offline_mode(device_id: Option<&str>) {
+        with(|state, _| {
+            state.tracker.disable_offline_mode(device_id);
+            store_device_id(state);
+        });
+    }
+
+    fn set_location(location: Location) {
+        with(|state, now| state.tracker.set_location(location, now));
+    }
+
+    fn disable_location() {
+        with(|state, now| state.tracker.disable_location(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use countly_mock::MockServer;
+    use super::*;
+
+    /// The tests share the global state, so they take turns.
+    fn serial() -> MutexGuard<'static, ()> {
+        static SERIAL: Mutex<()> = Mutex::new(());
+        SERIAL.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn event(key: &str) -> CustomEvent {
+        CustomEvent { key: key.to_owned(), count: 1, ..Default::default() }
+    }
+
+    fn configure(server: &MockServer) {
+        let mut config = Config::new("key", server.url());
+        config.device_id = Some("device".to_owned());
+        config.interval = Some(50.0);
+        Native::configure(config);
+    }
+
+    #[test]
+    fn flushes_and_shuts_down() {
+        let _serial = serial();
+        let server = MockServer::start();
+        configure(&server);
+        Native::add_event(event("flushed"));
+        assert!(Native::flush(Duration::from_secs(5)));
+        server.assert_event("flushed");
+
+        server.fail_all(Some(500));
+        Native::add_event(event("failed"));
+        assert!(!Native::flush(Duration::from_millis(200)));
+        Native::shutdown();
+        assert!(lock().is_none());
+
+        // The same with a runtime, where a task sends.
+        #[cfg(feature = "tokio")]
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            server.fail_all(None);
+            configure(&server);
+            Native::add_event(event("flushed async"));
+            tokio::time::timeout(Duration::from_secs(5), Native::flush_async()).await.unwrap();
+            server.assert_event("flushed async");
+
+            server.fail_all(Some(500));
+            Native::add_event(event("failed async"));
+            assert!(tokio::time::timeout(Duration::from_millis(200), Native::flush_async()).await.is_err());
+            Native::shutdown_async().await;
+            assert!(lock().is_none());
+        });
+    }
+
+    #[test]
+    fn sends_batches_and_backs_off() {
+        let _serial = serial();
+        let server = MockServer::start();
+        server.fail_next(2, 503);
+        let mut config = Config::new("key", server.url());
+        config.device_id = Some("device".to_owned());
+        config.interval = Some(200.0);
+        config.fail_timeout = Some(0.1);
+        config.max_events = Some(10);
+        Native::configure(config);
+        // After the first tick, so the events are all moved into requests at the next one.
+        thread::sleep(Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        for _ in 0..25 {
+            Native::add_event(event("click"));
+        }
+
+        assert!(server.wait_for(Duration::from_secs(5), |server| server.events().len() == 25));
+        // Paused for fail_timeout after the first failure and twice that after the second.
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(server.rejected(), 2);
+        let batches: Vec<usize> = server.requests().iter()
+            .filter_map(|request| request.get("events"))
+            .map(|events| serde_json::from_str::<Vec<Json>>(events).unwrap().len())
+            .collect();
+        assert_eq!(batches, [10, 10, 5]);
+        Native::shutdown();
+    }
+
+    #[test]
+    fn filters_views_by_route() {
+        let _serial = serial();
+        let server = MockServer::start();
+        configure(&server);
+        routes::configure(Some(crate::Routes::new().route("/users/:id")));
+        Native::track_pageview(Some("/users/12"), Some(&["/users/:id"]));
+        Native::track_pageview(Some("/orders/3"), Some(&["/users/:id"]));
+        routes::configure(None);
+        assert!(Native::flush(Duration::from_secs(5)));
+        Native::shutdown();
+        let views: Vec<_> = server.events().into_iter()
+            .filter(|event| event.key == "[CLY]_view")
+            .filter_map(|event| event.segmentation.get("name").cloned())
+            .collect();
+        assert_eq!(views, ["/orders/3"]);
+    }
+
+    #[test]
+    fn shuts_down_after_the_request_on_its_way() {
+        const ROUNDS: u64 = 100;
+        let _serial = serial();
+        let server = MockServer::start();
+        let dir = std::env::temp_dir().join(format!("countly-shutdown-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::new("key", server.url());
+        config.device_id = Some("device".to_owned());
+        config.interval = Some(0.001);
+        config.storage_dir = Some(dir.clone());
+
+        // Shutting down races the sender starting the next request, each round gives it another chance.
+        for round in 0..ROUNDS {
+            Native::configure(config.clone());
+            Native::add_event(event(&round.to_string()));
+            thread::sleep(Duration::from_millis(round % 3));
+            Native::shutdown();
+        }
+        Native::configure(config);
+        assert!(Native::flush(Duration::from_secs(5)));
+        Native::shutdown();
+        let mut sent: Vec<u64> = server.events().iter().map(|event| event.key.parse().unwrap()).collect();
+        sent.sort_unstable();
+        assert_eq!(sent, (0..ROUNDS).collect::<Vec<_>>());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,15 @@
+use crate::protocol::request::{Request, Server};
+
+/// Sends a request to the `/i` endpoint, returning an error message if the server didn't accept it.
+pub(super) fn send(server: &Server, request: &Request) -> Result<(), String> {
+    let endpoint = server.endpoint();
+    let query = server.encode(request);
+    let result = if server.use_post(&query) {
+        ureq::post(&endpoint)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .send(&query)
+    } else {
+        ureq::get(&format!("{}?{}", endpoint, query)).call()
+    };
+    result.map(|_| ()).map_err(|err| err.to_string())
+}
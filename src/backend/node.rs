@@ -0,0 +1,203 @@
+use std::{cell::Cell, collections::HashMap};
+use crate::{
+    Config,
+    auto_views::{AutoViewTracking, ViewName},
+    countly::{CustomEvent, Unsupported, UserDataOp},
+    countly_sys::node as CountlySys,
+    device_id::{DeviceIdChange, DeviceIdType},
+    gdpr::ConsentFeatures,
+    location::Location,
+    routes,
+    user_details::UserDetails,
+};
+use wasm_bindgen::{JsValue, JsCast};
+use js_sys::Reflect;
+use super::Backend;
+
+thread_local! {
+    static DEBUG: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Forwards everything to the Countly Node.js SDK, for wasm running under Node (server side rendering, serverless
+/// functions). There is no page, so everything that works on the DOM is unsupported.
+pub(crate) struct NodeSdk;
+
+impl NodeSdk {
+    /// For calls that can't fail, only logged with [Config::debug] like the SDK does.
+    fn unsupported(feature: &str) {
+        if DEBUG.with(Cell::get) {
+            web_sys::console::warn_1(&JsValue::from_str(&format!("[Countly] {} is not supported in Node.js", feature)));
+        }
+    }
+}
+
+impl Backend for NodeSdk {
+    fn configure(config: Config) {
+        DEBUG.with(|debug| debug.set(config.debug));
+        let js = JsValue::from_serde(&config).unwrap();
+        if let Some(dir) = &config.storage_dir {
+            let _ = Reflect::set(&js, &JsValue::from_str("storage_path"), &JsValue::from_str(&dir.to_string_lossy()));
+        }
+        CountlySys::init(js);
+    }
+
+    fn enable_session_tracking() {
+        CountlySys::track_sessions();
+    }
+
+    fn track_pageview(name: Option<&str>, filter: Option<&[&str]>) {
+        let name = match name {
+            Some(name) => routes::normalize(name),
+            None => return Self::unsupported("Tracking a pageview without a name"),
+        };
+        // Filtered by route, like the other backends.
+        if filter.map(|filter| filter.contains(&name.as_str())).unwrap_or(false) {
+            return;
+        }
+        CountlySys::track_view(&name);
+    }
+
+    fn set_view_name_callback(_callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Setting a view name callback", "node"))
+    }
+
+    fn set_view_url_callback(_callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Heatmaps", "node"))
+    }
+
+    fn enable_auto_view_tracking(_view_name: ViewName) -> Result<AutoViewTracking, Unsupported> {
+        Err(Unsupported::new("Automatic view tracking", "node"))
+    }
+
+    fn enable_link_tracking(_parent: Option<web_sys::Element>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Link tracking", "node"))
+    }
+
+    fn enable_form_submission_tracking(_parent: Option<&web_sys::Element>, _include_hidden: bool) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Form submission tracking", "node"))
+    }
+
+    fn enable_conversion_reporting(name: Option<&str>) {
+        match name {
+            Some(name) => CountlySys::report_conversion(name),
+            None => Self::unsupported("Reporting a conversion without a campaign id"),
+        }
+    }
+
+    fn opt_in() {
+        Self::unsupported("Opting in");
+    }
+
+    fn opt_out() {
+        Self::unsupported("Opting out");
+    }
+
+    fn enable_form_data_collection(_parent: Option<&web_sys::Element>, _custom_properties: bool) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from forms", "node"))
+    }
+
+    fn collect_from_facebook(_custom_properties: &HashMap<String, String>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from Facebook", "node"))
+    }
+
+    fn add_event(event: CustomEvent) {
+        CountlySys::add_event(JsValue::from_serde(&event).unwrap());
+    }
+
+    fn start_event(name: &str) {
+        CountlySys::start_event(name);
+    }
+
+    fn end_event(name: &str) {
+        CountlySys::end_event(name);
+    }
+
+    fn set_user_details(details: UserDetails) {
+        CountlySys::user_details(JsValue::from_serde(&details).unwrap());
+    }
+
+    fn user_data(key: &str, op: UserDataOp) {
+        match op {
+            UserDataOp::Set(value) => CountlySys::user_data_set(key, value.into()),
+            UserDataOp::Unset => CountlySys::user_data_unset(key),
+            UserDataOp::SetOnce(value) => CountlySys::user_data_set_once(key, value.into()),
+            UserDataOp::Increment => CountlySys::user_data_increment(key),
+            UserDataOp::IncrementBy(value) => CountlySys::user_data_increment_by(key, value),
+            UserDataOp::Multiply(value) => CountlySys::user_data_multiply(key, value),
+            UserDataOp::Max(value) => CountlySys::user_data_max(key, value),
+            UserDataOp::Min(value) => CountlySys::user_data_min(key, value),
+            UserDataOp::Push(value) => CountlySys::user_data_push(key, value.into()),
+            UserDataOp::PushUnique(value) => CountlySys::user_data_push_unique(key, value.into()),
+            UserDataOp::Pull(value) => CountlySys::user_data_pull(key, value.into()),
+        }
+    }
+
+    fn user_data_save() {
+        CountlySys::user_data_save();
+    }
+
+    fn enable_track_errors(segments: Option<HashMap<String, String>>) {
+        CountlySys::track_errors(segments.map(|segments| JsValue::from_serde(&segments).unwrap()).unwrap_or(JsValue::UNDEFINED));
+    }
+
+    fn log_error(error: JsValue, segments: Option<HashMap<String, String>>) {
+        CountlySys::log_error(error, segments.map(|segments| JsValue::from_serde(&segments).unwrap()).unwrap_or(JsValue::UNDEFINED));
+    }
+
+    fn add_log(msg: &str) {
+        CountlySys::add_log(msg);
+    }
+
+    fn device_id() -> Option<String> {
+        CountlySys::get_device_id().ok()?.as_string()
+    }
+
+    fn device_id_type() -> Option<DeviceIdType> {
+        let code = CountlySys::get_device_id_type().ok()?.as_f64()?;
+        DeviceIdType::from_code(code as u32)
+    }
+
+    fn change_device_id(id: &str, change: DeviceIdChange) {
+        CountlySys::change_id(id, change == DeviceIdChange::WithMerge);
+    }
+
+    fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>) {
+        CountlySys::group_features(JsValue::from_serde(&groups).unwrap());
+    }
+
+    fn add_consent(features: &[&str]) {
+        CountlySys::add_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+    }
+
+    fn remove_consent(features: &[&str]) {
+        CountlySys::remove_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+    }
+
+    fn begin_session(no_heart_beat: bool) {
+        CountlySys::begin_session(no_heart_beat);
+    }
+
+    fn extend_session(secs: f64) {
+        CountlySys::session_duration(secs);
+    }
+
+    fn end_session(secs: Option<f64>) {
+        CountlySys::end_session(secs.map(JsValue::from_f64).unwrap_or(JsValue::UNDEFINED));
+    }
+
+    fn enable_offline_mode() {
+        CountlySys::enable_offline_mode();
+    }
+
+    fn disable_offline_mode(device_id: Option<&str>) {
+        CountlySys::disable_offline_mode(device_id.map(JsValue::from_str).unwrap_or(JsValue::UNDEFINED));
+    }
+
+    fn set_location(_location: Location) {
+        Self::unsupported("Changing the location after configure");
+    }
+
+    fn disable_location() {
+        Self::unsupported("Disabling the location");
+    }
+}
@@ -0,0 +1,741 @@
+//! Implementation of the Countly protocol in Rust on top of `web_sys`, without loading the JavaScript SDK.
+
+mod storage;
+mod tabs;
+mod transport;
+
+use std::{cell::RefCell, collections::HashMap};
+use js_sys::Date;
+use serde_json::{Map, Value as Json};
+use wasm_bindgen::{JsValue, JsCast, closure::Closure};
+use crate::{
+    Config,
+    auto_views::{AutoViewTracking, ViewName},
+    protocol::{Now, queue::Queue, request::Server, scheduler::{Clock, Scheduler}, tracker::Tracker},
+    countly::{CustomEvent, Unsupported, UserDataOp, Value},
+    device_id::{DeviceIdChange, DeviceIdGenerator, DeviceIdType, UuidV4},
+    gdpr::ConsentFeatures,
+    location::Location,
+    profile_cache,
+    routes,
+    user_details::UserDetails,
+};
+use self::{storage::{LocalStorage, StoragePersist}, tabs::{Message, Tabs}};
+use super::Backend;
+
+const DEVICE_ID_KEY: &str = "cly_id";
+const DEVICE_ID_TYPE_KEY: &str = "cly_id_type";
+const IGNORE_KEY: &str = "cly_ignore";
+const CAMPAIGN_KEY: &str = "cly_cmp_id";
+/// Default for [Config::session_cookie_timeout], in minutes.
+const DEFAULT_SESSION_TIMEOUT: f64 = 30.0;
+
+/// An event listener with what it listens to, to remove it again.
+type Listener = (web_sys::EventTarget, &'static str, Closure<dyn FnMut(web_sys::Event)>);
+
+struct State {
+    tracker: Tracker<StoragePersist>,
+    storage: LocalStorage,
+    scheduler: Scheduler,
+    server: Server,
+    /// Sessions are begun and ended automatically.
+    track_sessions: bool,
+    /// Set with [Config::cross_tab].
+    tabs: Option<Tabs>,
+    /// The handle of the interval calling [tick], with the closure it calls.
+    timer: Option<(i32, Closure<dyn FnMut()>)>,
+    /// The event listeners, kept alive until they are removed again when the state is replaced.
+    listeners: Vec<Listener>,
+}
+
+/// Reconfiguring replaces the state, the browser must not call into the old one anymore.
+impl Drop for State {
+    fn drop(&mut self) {
+        let window = web_sys::window();
+        if let (Some(window), Some((handle, _))) = (&window, &self.timer) {
+            window.clear_interval_with_handle(*handle);
+        }
+        for (target, event, closure) in &self.listeners {
+            let _ = target.remove_event_listener_with_callback(event, closure.as_ref().unchecked_ref());
+        }
+        // Hands the session over to the other tabs, if this one leads.
+        if let Some(tabs) = &mut self.tabs {
+            tabs.leave(BrowserClock.now().timestamp);
+        }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+    static VIEW_NAME: RefCell<Option<Box<dyn FnMut() -> String>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` on the tracker, if [Countly::configure](crate::Countly::configure) has been called.
+fn with<R>(f: impl FnOnce(&mut State, Now) -> R) -> Option<R> {
+    STATE.with(|state| state.borrow_mut().as_mut().map(|state| f(state, BrowserClock.now())))
+}
+
+struct BrowserClock;
+
+impl Clock for BrowserClock {
+    fn now(&self) -> Now {
+        let date = Date::new_0();
+        Now {
+            timestamp: date.get_time() as u64,
+            hour: date.get_hours(),
+            dow: date.get_day(),
+            tz: -(date.get_timezone_offset() as i32),
+        }
+    }
+}
+
+fn debug_log(config: &Config, msg: &str) {
+    if config.debug {
+        web_sys::console::log_1(&JsValue::from_str(&format!("[Countly] {}", msg)));
+    }
+}
+
+/// Stores the device id after it might have changed and tells the other tabs if it did. With `restart_session`,
+/// they end the session for the old id and begin one for the new id.
+fn device_id_changed(state: &mut State, previous: &str, restart_session: bool) {
+    store_device_id(&state.storage, &state.tracker);
+    if let Some(tabs) = &mut state.tabs {
+        if state.tracker.device_id() != previous {
+            let id = state.tracker.device_id().to_owned();
+            tabs.send(Message::DeviceId { id, device_id_type: state.tracker.device_id_type().code(), restart_session });
+        }
+    }
+}
+
+/// The temporary id of offline mode is not stored, the previous one is restored after a reload.
+fn store_device_id(storage: &LocalStorage, tracker: &Tracker<StoragePersist>) {
+    let device_id_type = tracker.device_id_type();
+    if device_id_type != DeviceIdType::Temporary {
+        storage.set(DEVICE_ID_KEY, tracker.device_id());
+        storage.set(DEVICE_ID_TYPE_KEY, &device_id_type.code().to_string());
+    }
+}
+
+/// Ids stored before their type only come from the configuration or generation.
+fn load_device_id(storage: &LocalStorage) -> Option<(String, DeviceIdType)> {
+    let id = storage.get(DEVICE_ID_KEY)?;
+    let device_id_type = storage.get(DEVICE_ID_TYPE_KEY)
+        .and_then(|code| code.parse().ok())
+        .and_then(DeviceIdType::from_code)
+        .unwrap_or(DeviceIdType::SdkGenerated);
+    Some((id, device_id_type))
+}
+
+fn metrics(config: &Config) -> Map<String, Json> {
+    let mut metrics = Map::new();
+    if let Some(app_version) = &config.app_version {
+        metrics.insert("_app_version".to_owned(), Json::from(app_version.as_str()));
+    }
+    if let Some(window) = web_sys::window() {
+        let navigator = window.navigator();
+        if let Ok(ua) = navigator.user_agent() {
+            metrics.insert("_ua".to_owned(), Json::from(ua));
+        }
+        if let Some(locale) = navigator.language() {
+            metrics.insert("_locale".to_owned(), Json::from(locale));
+        }
+        if let Ok(screen) = window.screen() {
+            if let (Ok(width), Ok(height)) = (screen.width(), screen.height()) {
+                metrics.insert("_resolution".to_owned(), Json::from(format!("{}x{}", width, height)));
+            }
+        }
+        metrics.insert("_density".to_owned(), Json::from(window.device_pixel_ratio()));
+    }
+    metrics
+}
+
+fn location() -> Option<web_sys::Location> {
+    web_sys::window().map(|window| window.location())
+}
+
+/// Stores the campaign id if the visitor arrived through a Countly campaign link.
+fn store_campaign(storage: &LocalStorage) {
+    let search = location().and_then(|location| location.search().ok()).unwrap_or_default();
+    let campaign = form_urlencoded::parse(search.trim_start_matches('?').as_bytes())
+        .find(|(key, _)| key == "cly_id")
+        .map(|(_, value)| value.into_owned());
+    if let Some(campaign) = campaign {
+        storage.set(CAMPAIGN_KEY, &campaign);
+    }
+}
+
+fn js_error_to_string(error: &JsValue) -> String {
+    if let Some(error) = error.dyn_ref::<js_sys::Error>() {
+        let stack = js_sys::Reflect::get(error, &JsValue::from_str("stack")).ok().and_then(|stack| stack.as_string());
+        stack.unwrap_or_else(|| String::from(error.to_string()))
+    } else if let Some(error) = error.as_string() {
+        error
+    } else {
+        format!("{:?}", error)
+    }
+}
+
+/// Registers `callback` for `event` on `target`, as long as the tracker lives.
+fn listen(state: &mut State, target: &web_sys::EventTarget, event: &'static str, callback: impl FnMut(web_sys::Event) + 'static) {
+    let closure = Closure::wrap(Box::new(callback) as Box<dyn FnMut(web_sys::Event)>);
+    if target.add_event_listener_with_callback(event, closure.as_ref().unchecked_ref()).is_ok() {
+        state.listeners.push((target.clone(), event, closure));
+    }
+}
+
+/// With [Config::cross_tab], only the leading tab has a session. It continues the one of the previous leader, if that
+/// hasn't timed out.
+fn coordinate(state: &mut State, now: Now) {
+    let tabs = match &mut state.tabs {
+        Some(tabs) => tabs,
+        None => return,
+    };
+    let leader = tabs.poll(now.timestamp, state.tracker.is_paused());
+    if !state.track_sessions {
+        return;
+    }
+    if !leader {
+        state.tracker.suspend_session();
+        return;
+    }
+    if state.tracker.is_paused() {
+        // Only the session ending for inactivity matters until the page is visible again.
+        tabs.set_session_beat(state.tracker.session_beat());
+        return;
+    }
+    if state.tracker.session_beat().is_none() {
+        match tabs.session_beat(now.timestamp) {
+            Some(beat) => state.tracker.resume_session(beat),
+            None => state.tracker.begin_session(true, now),
+        }
+    }
+    tabs.set_session_beat(state.tracker.session_beat());
+}
+
+/// A message from another tab, which already reported the change to the server.
+fn received(event: web_sys::Event) {
+    with(|state, now| {
+        let message = match state.tabs.as_ref().and_then(|tabs| tabs.receive(&event)) {
+            Some(message) => message,
+            None => return,
+        };
+        match message {
+            Message::DeviceId { id, device_id_type, restart_session } => {
+                if let Some(device_id_type) = DeviceIdType::from_code(device_id_type) {
+                    profile_cache::clear();
+                    state.tracker.adopt_device_id(&id, device_id_type, restart_session, now);
+                }
+            }
+            Message::Consent { features, granted } => {
+                let features: Vec<&str> = features.iter().map(String::as_str).collect();
+                state.tracker.sync_consent(&features, granted, now);
+            }
+            Message::Leaving => coordinate(state, now),
+        }
+    });
+}
+
+/// Called periodically: records heartbeats and sends the next request from the queue.
+fn tick() {
+    let next = with(|state, now| {
+        coordinate(state, now);
+        let request = state.scheduler.poll(&mut state.tracker, now)?;
+        Some((state.server.clone(), request))
+    }).flatten();
+    if let Some((server, request)) = next {
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = transport::send(&server, &request).await;
+            with(|state, now| {
+                if let Err(err) = &result {
+                    debug_log(&state.tracker.config, &format!("Request failed: {:?}", err));
+                }
+                state.scheduler.complete(&mut state.tracker, &request, result.is_ok(), now);
+            });
+        });
+    }
+}
+
+/// The page was hidden or frozen: the session and timed events are paused, and the session ends if the page isn't
+/// visible again within [Config::inactivity_time]. With other tabs open, the session is left to them.
+fn hidden() {
+    with(|state, now| {
+        state.tracker.pause(now);
+        if let Some(tabs) = &mut state.tabs {
+            tabs.set_session_beat(state.tracker.session_beat());
+            if tabs.release(now.timestamp) {
+                state.tracker.suspend_session();
+            }
+        }
+    });
+}
+
+/// The page is visible again: continues the session or begins a new one, if sessions are tracked automatically.
+fn shown() {
+    with(|state, now| {
+        state.tracker.resume(now);
+        if !state.track_sessions {
+            return;
+        }
+        if state.tabs.is_some() {
+            coordinate(state, now);
+        } else {
+            state.tracker.begin_session(true, now);
+        }
+    });
+}
+
+fn visibility_changed() {
+    let hidden_now = web_sys::window().and_then(|window| window.document()).map(|document| document.hidden());
+    match hidden_now {
+        Some(true) => hidden(),
+        Some(false) => shown(),
+        None => {}
+    }
+}
+
+/// The page is going away: wrap up and hand everything left to `sendBeacon`. With other tabs still open, the session
+/// is left to them instead of ending it.
+fn unload() {
+    with(|state, now| {
+        let others = state.tabs.as_mut().map(|tabs| tabs.leave(now.timestamp)).unwrap_or(false);
+        if state.track_sessions && !others {
+            state.tracker.end_session(None, now);
+            if let Some(tabs) = &state.tabs {
+                tabs.set_session_beat(None);
+            }
+        } else {
+            if state.tracker.session_beat().is_some() && others {
+                state.tracker.extend_session(None, now);
+                if let Some(tabs) = &state.tabs {
+                    tabs.set_session_beat(state.tracker.session_beat());
+                }
+                state.tracker.suspend_session();
+            }
+            state.tracker.end_view(now);
+            state.tracker.flush_events(now);
+        }
+        if state.tracker.is_offline() || state.scheduler.is_sending() {
+            return;
+        }
+        while let Some(request) = state.tracker.queue.front() {
+            if !transport::beacon(&state.server, request) {
+                break;
+            }
+            state.tracker.queue.pop_front();
+        }
+    });
+}
+
+/// Records DOM events as custom events, used by link and form tracking.
+fn add_event(key: &str, segmentation: HashMap<String, Value>) {
+    with(|state, now| {
+        state.tracker.add_event(CustomEvent { key: key.to_owned(), count: 1, segmentation, ..Default::default() }, now);
+    });
+}
+
+fn link_clicked(event: web_sys::Event) {
+    let link = event.target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .and_then(|element| element.closest("a").ok().flatten())
+        .and_then(|element| element.dyn_into::<web_sys::HtmlAnchorElement>().ok());
+    if let Some(link) = link {
+        let mut segmentation = HashMap::new();
+        segmentation.insert("href".to_owned(), routes::normalize(&link.href()).into());
+        segmentation.insert("text".to_owned(), link.text().unwrap_or_default().trim().to_owned().into());
+        segmentation.insert("id".to_owned(), link.id().into());
+        if let Some(domain) = location().and_then(|location| location.hostname().ok()) {
+            segmentation.insert("domain".to_owned(), domain.into());
+        }
+        add_event("linkClick", segmentation);
+    }
+}
+
+fn form_submitted(event: web_sys::Event, include_hidden: bool) {
+    let form = match event.target().and_then(|target| target.dyn_into::<web_sys::HtmlFormElement>().ok()) {
+        Some(form) => form,
+        None => return,
+    };
+    let mut segmentation = HashMap::new();
+    segmentation.insert("id".to_owned(), form.id().into());
+    segmentation.insert("name".to_owned(), form.name().into());
+    segmentation.insert("action".to_owned(), form.action().into());
+    segmentation.insert("method".to_owned(), form.method().into());
+    if let Ok(inputs) = form.query_selector_all("input[name]") {
+        for idx in 0..inputs.length() {
+            let input = match inputs.item(idx).and_then(|node| node.dyn_into::<web_sys::HtmlInputElement>().ok()) {
+                Some(input) => input,
+                None => continue,
+            };
+            let kind = input.type_();
+            if kind == "password" || (kind == "hidden" && !include_hidden) {
+                continue;
+            }
+            if (kind == "checkbox" || kind == "radio") && !input.checked() {
+                continue;
+            }
+            segmentation.insert(format!("input:{}", input.name()), input.value().into());
+        }
+    }
+    add_event("formSubmit", segmentation);
+}
+
+fn error_caught(event: web_sys::Event) {
+    let error = if let Some(event) = event.dyn_ref::<web_sys::ErrorEvent>() {
+        let error = event.error();
+        if error.is_undefined() || error.is_null() {
+            event.message()
+        } else {
+            js_error_to_string(&error)
+        }
+    } else if let Some(event) = event.dyn_ref::<web_sys::PromiseRejectionEvent>() {
+        js_error_to_string(&event.reason())
+    } else {
+        return;
+    };
+    with(|state, now| state.tracker.log_error(&error, false, None, now));
+}
+
+/// The browser implementation, see the module documentation.
+pub(crate) struct Pure;
+
+impl Backend for Pure {
+    fn configure(config: Config) {
+        let prefix = config.namespace.clone().unwrap_or_else(|| config.app_key.clone());
+        let storage = LocalStorage::new(&prefix);
+        let (device_id, device_id_type) = load_device_id(&storage).unwrap_or_else(|| {
+            let id = match &config.device_id_generator {
+                Some(generator) => generator.generate(),
+                None => UuidV4.generate(),
+            };
+            (id, DeviceIdType::SdkGenerated)
+        });
+        let queue = Queue::new(config.queue_size, StoragePersist(LocalStorage::new(&prefix)));
+        let scheduler = Scheduler::new(&config, (js_sys::Math::random() * u64::MAX as f64) as u64);
+        let tabs = if config.cross_tab {
+            let session_timeout = config.session_cookie_timeout.unwrap_or(DEFAULT_SESSION_TIMEOUT) * 60_000.0;
+            Some(Tabs::new(&prefix, scheduler.interval(), session_timeout as u64))
+        } else {
+            None
+        };
+        let server = Server::new(&config);
+        let metrics = metrics(&config);
+        let mut tracker = Tracker::new(config, queue, device_id, device_id_type, "Web", metrics);
+        if storage.get(IGNORE_KEY).is_some() {
+            tracker.set_ignored(true);
+        }
+        store_device_id(&storage, &tracker);
+        store_campaign(&storage);
+
+        let mut state = State {
+            tracker,
+            storage,
+            scheduler,
+            server,
+            track_sessions: false,
+            tabs,
+            timer: None,
+            listeners: Vec::new(),
+        };
+        if let Some(window) = web_sys::window() {
+            let timer = Closure::wrap(Box::new(tick) as Box<dyn FnMut()>);
+            let interval = state.scheduler.interval() as i32;
+            state.timer = window
+                .set_interval_with_callback_and_timeout_and_arguments_0(timer.as_ref().unchecked_ref(), interval)
+                .ok()
+                .map(|handle| (handle, timer));
+            listen(&mut state, &window, "pagehide", |_| unload());
+            // Also after coming back from the back/forward cache, where the session was ended by `pagehide`.
+            listen(&mut state, &window, "pageshow", |_| shown());
+            if let Some(document) = window.document() {
+                listen(&mut state, &document, "visibilitychange", |_| visibility_changed());
+                listen(&mut state, &document, "freeze", |_| hidden());
+                listen(&mut state, &document, "resume", |_| shown());
+            }
+            match state.tabs.as_ref().map(Tabs::channel) {
+                Some(Some(channel)) => listen(&mut state, &channel, "message", received),
+                Some(None) => listen(&mut state, &window, "storage", received),
+                None => {}
+            }
+        }
+        // Opened in the background, there won't be a `visibilitychange` until it's shown.
+        if web_sys::window().and_then(|window| window.document()).map(|document| document.hidden()).unwrap_or(false) {
+            state.tracker.pause(BrowserClock.now());
+        }
+        debug_log(&state.tracker.config, "Initialized");
+        // Dropping the previous state removes its timer and listeners, outside of the borrow.
+        let previous = STATE.with(|cell| cell.replace(Some(state)));
+        drop(previous);
+    }
+
+    fn enable_session_tracking() {
+        with(|state, now| {
+            state.track_sessions = true;
+            if state.tabs.is_some() {
+                coordinate(state, now);
+            } else {
+                state.tracker.begin_session(true, now);
+            }
+        });
+    }
+
+    fn track_pageview(name: Option<&str>, filter: Option<&[&str]>) {
+        let name = match name {
+            Some(name) => name.to_owned(),
+            None => VIEW_NAME.with(|callback| callback.borrow_mut().as_mut().map(|callback| callback()))
+                .or_else(|| location().and_then(|location| location.pathname().ok()))
+                .unwrap_or_default(),
+        };
+        let name = routes::normalize(&name);
+        // Only exact matches, the Web SDK would also accept regular expressions here.
+        if filter.map(|filter| filter.contains(&name.as_str())).unwrap_or(false) {
+            return;
+        }
+        let domain = location().and_then(|location| location.hostname().ok());
+        with(|state, now| state.tracker.track_view(&name, domain.as_deref(), now));
+    }
+
+    fn set_view_name_callback(callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        VIEW_NAME.with(|cell| *cell.borrow_mut() = callback);
+        Ok(())
+    }
+
+    fn set_view_url_callback(_callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        // The view URL is only used for heatmaps.
+        Err(Unsupported::new("Heatmaps", "pure"))
+    }
+
+    fn enable_auto_view_tracking(view_name: ViewName) -> Result<AutoViewTracking, Unsupported> {
+        AutoViewTracking::start(view_name, |name| Self::track_pageview(Some(name), None))
+            .ok_or(Unsupported::new("Automatic view tracking outside a browser", "pure"))
+    }
+
+    fn enable_link_tracking(parent: Option<web_sys::Element>) -> Result<(), Unsupported> {
+        let target: Option<web_sys::EventTarget> = match parent {
+            Some(parent) => Some(parent.into()),
+            None => web_sys::window().and_then(|window| window.document()).map(Into::into),
+        };
+        if let Some(target) = target {
+            with(|state, _| listen(state, &target, "click", link_clicked));
+        }
+        Ok(())
+    }
+
+    fn enable_form_submission_tracking(parent: Option<&web_sys::Element>, include_hidden: bool) -> Result<(), Unsupported> {
+        let target: Option<web_sys::EventTarget> = match parent {
+            Some(parent) => Some(parent.clone().into()),
+            None => web_sys::window().and_then(|window| window.document()).map(Into::into),
+        };
+        if let Some(target) = target {
+            with(|state, _| listen(state, &target, "submit", move |event| form_submitted(event, include_hidden)));
+        }
+        Ok(())
+    }
+
+    fn enable_conversion_reporting(name: Option<&str>) {
+        with(|state, now| {
+            let campaign = name.map(str::to_owned).or_else(|| state.storage.get(CAMPAIGN_KEY));
+            if let Some(campaign) = campaign {
+                state.tracker.report_conversion(&campaign, now);
+                state.storage.remove(CAMPAIGN_KEY);
+            }
+        });
+    }
+
+    fn opt_in() {
+        with(|state, _| {
+            state.storage.remove(IGNORE_KEY);
+            state.tracker.set_ignored(false);
+        });
+    }
+
+    fn opt_out() {
+        with(|state, _| {
+            state.storage.set(IGNORE_KEY, "true");
+            state.tracker.set_ignored(true);
+        });
+    }
+
+    fn enable_form_data_collection(_parent: Option<&web_sys::Element>, _custom_properties: bool) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from forms", "pure"))
+    }
+
+    fn collect_from_facebook(_custom_properties: &HashMap<String, String>) -> Result<(), Unsupported> {
+        Err(Unsupported::new("Collecting user data from Facebook", "pure"))
+    }
+
+    fn add_event(event: CustomEvent) {
+        with(|state, now| state.tracker.add_event(event, now));
+    }
+
+    fn start_event(name: &str) {
+        with(|state, now| state.tracker.start_event(name, now));
+    }
+
+    fn end_event(name: &str) {
+        with(|state, now| state.tracker.end_event(name, now));
+    }
+
+    fn set_user_details(details: UserDetails) {
+        with(|state, now| state.tracker.user_details(&details, now));
+    }
+
+    fn user_data(key: &str, op: UserDataOp) {
+        with(|state, _| state.tracker.user_data(key, op));
+    }
+
+    fn user_data_save() {
+        with(|state, now| state.tracker.user_data_save(now));
+    }
+
+    fn enable_track_errors(segments: Option<HashMap<String, String>>) {
+        let window = match web_sys::window() {
+            Some(window) => window,
+            None => return,
+        };
+        with(|state, _| {
+            state.tracker.set_crash_segments(segments);
+            listen(state, &window, "error", error_caught);
+            listen(state, &window, "unhandledrejection", error_caught);
+        });
+    }
+
+    fn log_error(error: JsValue, segments: Option<HashMap<String, String>>) {
+        let error = js_error_to_string(&error);
+        with(|state, now| state.tracker.log_error(&error, true, segments, now));
+    }
+
+    fn add_log(msg: &str) {
+        with(|state, _| state.tracker.add_log(msg));
+    }
+
+    fn device_id() -> Option<String> {
+        with(|state, _| state.tracker.device_id().to_owned())
+    }
+
+    fn device_id_type() -> Option<DeviceIdType> {
+        with(|state, _| state.tracker.device_id_type())
+    }
+
+    fn change_device_id(id: &str, change: DeviceIdChange) {
+        with(|state, now| {
+            let previous = state.tracker.device_id().to_owned();
+            state.tracker.change_device_id(id, change, now);
+            device_id_changed(state, &previous, change == DeviceIdChange::WithoutMerge);
+        });
+    }
+
+    fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>) {
+        with(|state, _| state.tracker.group_features(groups));
+    }
+
+    fn add_consent(features: &[&str]) {
+        with(|state, now| {
+            state.tracker.add_consent(features, now);
+            if let Some(tabs) = &mut state.tabs {
+                tabs.send(Message::Consent { features: features.iter().map(|feature| (*feature).to_owned()).collect(), granted: true });
+            }
+        });
+    }
+
+    fn remove_consent(features: &[&str]) {
+        with(|state, now| {
+            state.tracker.remove_consent(features, now);
+            if let Some(tabs) = &mut state.tabs {
+                tabs.send(Message::Consent { features: features.iter().map(|feature| (*feature).to_owned()).collect(), granted: false });
+            }
+        });
+    }
+
+    fn begin_session(no_heart_beat: bool) {
+        with(|state, now| state.tracker.begin_session(!no_heart_beat, now));
+    }
+
+    fn extend_session(secs: f64) {
+        with(|state, now| state.tracker.extend_session(Some(secs), now));
+    }
+
+    fn end_session(secs: Option<f64>) {
+        with(|state, now| state.tracker.end_session(secs, now));
+    }
+
+    fn enable_offline_mode() {
+        with(|state, now| {
+            let previous = state.tracker.device_id().to_owned();
+            state.tracker.enable_offline_mode(now);
+            device_id_changed(state, &previous, false);
+        });
+    }
+
+    fn disable_offline_mode(device_id: Option<&str>) {
+        with(|state, _| {
+            let previous = state.tracker.device_id().to_owned();
+            state.tracker.disable_offline_mode(device_id);
+            device_id_changed(state, &previous, false);
+        });
+    }
+
+    fn set_location(location: Location) {
+        with(|state, now| state.tracker.set_location(location, now));
+    }
+
+    fn disable_location() {
+        with(|state, now| state.tracker.disable_location(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: u64) -> Now {
+        Now { timestamp, hour: 0, dow: 0, tz: 0 }
+    }
+
+    #[test]
+    fn pauses_while_hidden() {
+        let mut config = Config::new("key", "http://localhost");
+        config.inactivity_time = Some(1.0);
+        let queue = Queue::new(None, None::<StoragePersist>);
+        let mut tracker = Tracker::new(config, queue, "device".to_owned(), DeviceIdType::SdkGenerated, "Web", Map::new());
+
+        tracker.begin_session(true, at(0));
+        tracker.start_event("video", at(0));
+        tracker.pause(at(30_000));
+        tracker.tick(at(60_000));
+        tracker.resume(at(70_000));
+        tracker.end_event("video", at(80_000));
+        tracker.pause(at(80_000));
+        tracker.tick(at(150_000));
+        tracker.resume(at(160_000));
+
+        let requests: Vec<_> = tracker.queue.iter().collect();
+        let durations: Vec<_> = requests.iter().filter_map(|request| request.get("session_duration")).collect();
+        assert_eq!(durations, ["30", "10", "0"]);
+        let events: Vec<Json> = serde_json::from_str(requests[3].get("events").unwrap()).unwrap();
+        assert_eq!(events[0]["dur"], 40.0);
+        assert_eq!(requests[4].get("end_session"), Some("1"));
+        assert_eq!(requests[4].get("timestamp"), Some("80000"));
+        assert_eq!(requests[5].get("begin_session"), Some("1"));
+        assert!(!tracker.is_paused());
+    }
+
+    #[test]
+    fn restores_view_name_callbacks() {
+        let view_name = || VIEW_NAME.with(|callback| callback.borrow_mut().as_mut().map(|callback| callback()));
+
+        let home = crate::Countly::set_view_name_callback(|| "home".to_owned()).unwrap();
+        let about = crate::Countly::set_view_name_callback(|| "about".to_owned()).unwrap();
+        assert_eq!(view_name().as_deref(), Some("about"));
+        home.replace(|| "start".to_owned());
+        assert_eq!(view_name().as_deref(), Some("about"));
+        drop(about);
+        assert_eq!(view_name().as_deref(), Some("start"));
+
+        let contact = crate::Countly::set_view_name_callback(|| "contact".to_owned()).unwrap();
+        home.remove();
+        assert_eq!(view_name().as_deref(), Some("contact"));
+        drop(contact);
+        assert_eq!(view_name(), None);
+        assert!(crate::Countly::set_view_url_callback(|| "/".to_owned()).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+use crate::protocol::{queue::Persist, request::Request};
+
+/// `localStorage`, with all keys prefixed by the namespace (or app key) like the Web SDK does.
+pub(super) struct LocalStorage {
+    prefix: String,
+    storage: Option<web_sys::Storage>,
+}
+
+impl LocalStorage {
+    pub fn new(prefix: &str) -> Self {
+        let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+        Self { prefix: prefix.to_owned(), storage }
+    }
+
+    pub fn key(&self, key: &str) -> String {
+        format!("{}/{}", self.prefix, key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.storage.as_ref()?.get_item(&self.key(key)).ok().flatten()
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        if let Some(storage) = &self.storage {
+            // Quota errors are not fatal, the data just doesn't survive a reload.
+            let _ = storage.set_item(&self.key(key), value);
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Some(storage) = &self.storage {
+            let _ = storage.remove_item(&self.key(key));
+        }
+    }
+}
+
+/// Keeps the request queue in `localStorage` as a JSON array.
+pub(super) struct StoragePersist(pub LocalStorage);
+
+const QUEUE_KEY: &str = "cly_queue";
+
+impl Persist for StoragePersist {
+    fn load(&mut self) -> Vec<Request> {
+        self.0.get(QUEUE_KEY)
+            .and_then(|queue| serde_json::from_str(&queue).ok())
+            .unwrap_or_default()
+    }
+
+    fn store(&mut self, queue: &VecDeque<Request>) {
+        if queue.is_empty() {
+            self.0.remove(QUEUE_KEY);
+        } else if let Ok(queue) = serde_json::to_string(queue) {
+            self.0.set(QUEUE_KEY, &queue);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Coordination between the tabs of a site, enabled with [Config::cross_tab](crate::Config::cross_tab).
+//!
+//! The device id is shared through `localStorage` anyway. On top of that, the tab holding the lease in `localStorage`
+//! leads and is the only one with a session, so all tabs together have one. Another tab takes over when the leader is
+//! closed or stops renewing the lease. Device id and consent changes are sent to the other tabs with a
+//! `BroadcastChannel`, or as `storage` events where that is missing.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use wasm_bindgen::{JsValue, JsCast};
+use crate::device_id::{DeviceIdGenerator, UuidV4};
+use super::storage::LocalStorage;
+
+const LEADER_KEY: &str = "cly_tab_leader";
+const TABS_KEY: &str = "cly_tabs";
+const SESSION_KEY: &str = "cly_tab_session";
+const MESSAGE_KEY: &str = "cly_tab_message";
+/// How long the lease and the presence of a tab last without being renewed, at least. Renewed on every tick, in
+/// milliseconds.
+const MIN_TIMEOUT: u64 = 5_000;
+
+/// Sent to the other tabs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum Message {
+    /// `device_id_type` as in [DeviceIdType::code](crate::device_id::DeviceIdType::code).
+    DeviceId { id: String, device_id_type: u32, restart_session: bool },
+    Consent { features: Vec<String>, granted: bool },
+    /// The leader is closing, another tab should take over right away.
+    Leaving,
+}
+
+/// `seq` makes every message different, `storage` events only fire for changed values.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    tab: String,
+    seq: u64,
+    message: Message,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Lease {
+    tab: String,
+    until: u64,
+}
+
+/// Whether `tab` may renew the lease or, unless it's hidden, take it.
+fn may_lead(tab: &str, lease: Option<&Lease>, hidden: bool, now: u64) -> bool {
+    match lease {
+        Some(lease) if lease.tab == tab => true,
+        _ if hidden => false,
+        Some(lease) => lease.until <= now,
+        None => true,
+    }
+}
+
+/// The message if it was sent by another tab.
+fn decode(tab: &str, encoded: &str) -> Option<Message> {
+    let envelope: Envelope = serde_json::from_str(encoded).ok()?;
+    if envelope.tab == tab {
+        None
+    } else {
+        Some(envelope.message)
+    }
+}
+
+pub(super) struct Tabs {
+    id: String,
+    storage: LocalStorage,
+    channel: Option<web_sys::BroadcastChannel>,
+    timeout: u64,
+    /// How long a session lasts without a heartbeat from any tab, in milliseconds.
+    session_timeout: u64,
+    leader: bool,
+    seq: u64,
+}
+
+impl Tabs {
+    /// `interval` is how often [Tabs::poll] is called, in milliseconds.
+    pub fn new(prefix: &str, interval: u64, session_timeout: u64) -> Self {
+        Self {
+            id: UuidV4.generate(),
+            storage: LocalStorage::new(prefix),
+            channel: web_sys::BroadcastChannel::new(&format!("{}/{}", prefix, MESSAGE_KEY)).ok(),
+            timeout: MIN_TIMEOUT.max(3 * interval),
+            session_timeout,
+            leader: false,
+            seq: 0,
+        }
+    }
+
+    /// Where messages from other tabs arrive as `message` events. Without one, they are `storage` events of the window.
+    pub fn channel(&self) -> Option<web_sys::BroadcastChannel> {
+        self.channel.clone()
+    }
+
+    fn load<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.storage.get(key).and_then(|value| serde_json::from_str(&value).ok())
+    }
+
+    fn store<T: Serialize>(&self, key: &str, value: &T) {
+        if let Ok(value) = serde_json::to_string(value) {
+            self.storage.set(key, &value);
+        }
+    }
+
+    /// The open tabs with when they were last seen.
+    fn open_tabs(&self, now: u64) -> HashMap<String, u64> {
+        let mut tabs: HashMap<String, u64> = self.load(TABS_KEY).unwrap_or_default();
+        tabs.retain(|_, seen| now < *seen + self.timeout);
+        tabs
+    }
+
+    /// Registers this tab as open and takes or renews the lease if possible. Returns whether this tab leads. A hidden
+    /// tab only keeps the lease it has.
+    pub fn poll(&mut self, now: u64, hidden: bool) -> bool {
+        let mut tabs = self.open_tabs(now);
+        tabs.insert(self.id.clone(), now);
+        self.store(TABS_KEY, &tabs);
+        let lease: Option<Lease> = self.load(LEADER_KEY);
+        self.leader = may_lead(&self.id, lease.as_ref(), hidden, now);
+        if self.leader {
+            self.store(LEADER_KEY, &Lease { tab: self.id.clone(), until: now + self.timeout });
+        }
+        self.leader
+    }
+
+    /// This tab was hidden. If others are open, the lease is left to one of them and `true` returned.
+    pub fn release(&mut self, now: u64) -> bool {
+        if !self.leader || self.open_tabs(now).keys().all(|tab| *tab == self.id) {
+            return false;
+        }
+        self.leader = false;
+        self.storage.remove(LEADER_KEY);
+        self.send(Message::Leaving);
+        true
+    }
+
+    /// This tab is closing. Returns whether others are still open, then the lease is left to one of them.
+    pub fn leave(&mut self, now: u64) -> bool {
+        let mut tabs = self.open_tabs(now);
+        tabs.remove(&self.id);
+        self.store(TABS_KEY, &tabs);
+        if self.leader {
+            self.leader = false;
+            self.storage.remove(LEADER_KEY);
+            self.send(Message::Leaving);
+        }
+        !tabs.is_empty()
+    }
+
+    /// When the shared session was begun or last extended, unless it timed out.
+    pub fn session_beat(&self, now: u64) -> Option<u64> {
+        self.storage.get(SESSION_KEY)
+            .and_then(|beat| beat.parse::<u64>().ok())
+            .filter(|beat| now < beat + self.session_timeout)
+    }
+
+    /// `None` once the session ended.
+    pub fn set_session_beat(&self, beat: Option<u64>) {
+        match beat {
+            Some(beat) => self.storage.set(SESSION_KEY, &beat.to_string()),
+            None => self.storage.remove(SESSION_KEY),
+        }
+    }
+
+    pub fn send(&mut self, message: Message) {
+        self.seq += 1;
+        let envelope = Envelope { tab: self.id.clone(), seq: self.seq, message };
+        let encoded = match serde_json::to_string(&envelope) {
+            Ok(encoded) => encoded,
+            Err(_) => return,
+        };
+        match &self.channel {
+            Some(channel) => {
+                let _ = channel.post_message(&JsValue::from_str(&encoded));
+            }
+            None => self.storage.set(MESSAGE_KEY, &encoded),
+        }
+    }
+
+    /// The message in a `message` event of the channel or a `storage` event of the window, if it's from another tab.
+    pub fn receive(&self, event: &web_sys::Event) -> Option<Message> {
+        let encoded = if let Some(event) = event.dyn_ref::<web_sys::MessageEvent>() {
+            event.data().as_string()?
+        } else if let Some(event) = event.dyn_ref::<web_sys::StorageEvent>() {
+            if event.key()? != self.storage.key(MESSAGE_KEY) {
+                return None;
+            }
+            event.new_value()?
+        } else {
+            return None;
+        };
+        decode(&self.id, &encoded)
+    }
+}
+
+impl Drop for Tabs {
+    fn drop(&mut self) {
+        if let Some(channel) = &self.channel {
+            channel.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+    use crate::{
+        Config,
+        device_id::DeviceIdType,
+        protocol::{Now, queue::Queue, tracker::Tracker},
+    };
+    use super::{super::storage::StoragePersist, *};
+
+    #[test]
+    fn elects_and_decodes() {
+        let lease = Lease { tab: "a".to_owned(), until: 1000 };
+        assert!(may_lead("a", None, false, 0));
+        assert!(may_lead("a", Some(&lease), false, 500));
+        assert!(may_lead("a", Some(&lease), true, 500));
+        assert!(!may_lead("b", Some(&lease), false, 500));
+        assert!(may_lead("b", Some(&lease), false, 1000));
+        assert!(!may_lead("b", Some(&lease), true, 1000));
+        assert!(!may_lead("b", None, true, 0));
+
+        let message = Message::Consent { features: vec!["sessions".to_owned()], granted: true };
+        let encoded = serde_json::to_string(&Envelope { tab: "a".to_owned(), seq: 1, message: message.clone() }).unwrap();
+        assert_eq!(decode("a", &encoded), None);
+        assert_eq!(decode("b", &encoded), Some(message));
+    }
+
+    #[test]
+    fn follows_other_tabs() {
+        let mut config = Config::new("key", "http://localhost");
+        config.require_consent = true;
+        let queue = Queue::new(None, None::<StoragePersist>);
+        let mut tracker = Tracker::new(config, queue, "device".to_owned(), DeviceIdType::SdkGenerated, "Web", Map::new());
+        let now = Now { timestamp: 1000, hour: 0, dow: 0, tz: 0 };
+
+        tracker.sync_consent(&["sessions"], true, now);
+        tracker.resume_session(500);
+        assert_eq!(tracker.session_beat(), Some(500));
+        assert_eq!(tracker.queue.iter().count(), 0);
+
+        tracker.adopt_device_id("user", DeviceIdType::DeveloperSupplied, true, now);
+        assert_eq!((tracker.device_id(), tracker.device_id_type()), ("user", DeviceIdType::DeveloperSupplied));
+        let requests: Vec<_> = tracker.queue.iter().collect();
+        assert_eq!((requests[0].get("end_session"), requests[0].get("device_id")), (Some("1"), Some("device")));
+        assert_eq!((requests[1].get("begin_session"), requests[1].get("device_id")), (Some("1"), Some("user")));
+
+        tracker.sync_consent(&["sessions"], false, now);
+        assert_eq!(tracker.session_beat(), None);
+        assert_eq!(tracker.queue.iter().last().unwrap().get("end_session"), Some("1"));
+        assert!(tracker.queue.iter().all(|request| request.get("consent").is_none()));
+    }
+}
@@ -0,0 +1,41 @@
+use wasm_bindgen::{JsValue, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{RequestInit, Response};
+use crate::protocol::request::{Request, Server};
+
+/// Sends a request via `fetch`, resolving to `Ok` if the server accepted it.
+pub(super) async fn send(server: &Server, request: &Request) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    let endpoint = server.endpoint();
+    let query = server.encode(request);
+    let init = RequestInit::new();
+    let fetch_request = if server.use_post(&query) {
+        init.set_method("POST");
+        init.set_body(&JsValue::from_str(&query));
+        let fetch_request = web_sys::Request::new_with_str_and_init(&endpoint, &init)?;
+        fetch_request.headers().set("Content-Type", "application/x-www-form-urlencoded")?;
+        fetch_request
+    } else {
+        init.set_method("GET");
+        web_sys::Request::new_with_str_and_init(&format!("{}?{}", endpoint, query), &init)?
+    };
+    let response: Response = JsFuture::from(window.fetch_with_request(&fetch_request)).await?.unchecked_into();
+    if response.ok() {
+        Ok(())
+    } else {
+        Err(JsValue::from_f64(response.status() as f64))
+    }
+}
+
+/// Hands a request to `navigator.sendBeacon`, which also works while the page is being unloaded.
+///
+/// Returns `false` if the browser refused to queue it.
+pub(super) fn beacon(server: &Server, request: &Request) -> bool {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return false,
+    };
+    // Beacons are POSTed as text/plain, which the server doesn't parse, so the parameters go into the URL.
+    let endpoint = format!("{}?{}", server.endpoint(), server.encode(request));
+    window.navigator().send_beacon(&endpoint).unwrap_or(false)
+}
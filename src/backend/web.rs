@@ -0,0 +1,307 @@
+use std::{cell::RefCell, collections::HashMap};
+use crate::{
+    Config,
+    auto_views::{AutoViewTracking, ViewName},
+    countly::{CustomEvent, Unsupported, UserDataOp, Value},
+    countly_sys::Countly as CountlySys,
+    device_id::{DeviceIdChange, DeviceIdType},
+    gdpr::ConsentFeatures,
+    location::Location,
+    routes,
+    user_details::UserDetails,
+};
+use wasm_bindgen::{JsValue, JsCast, closure::Closure};
+use js_sys::{Array, Function};
+use super::Backend;
+
+/// A closure set as getter of the Web SDK, with the getter it replaced.
+type Getter = Option<(Closure<dyn FnMut() -> String>, JsValue)>;
+
+thread_local! {
+    static VIEW_NAME: RefCell<Getter> = const { RefCell::new(None) };
+    static VIEW_URL: RefCell<Getter> = const { RefCell::new(None) };
+    /// Wrap the original getters once routes are configured, they read the current ones.
+    static ROUTED: RefCell<Vec<Closure<dyn FnMut() -> String>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Sets `callback` as getter, or the original one of the Web SDK for `None`. The closure that was set before is only
+/// dropped once it has been replaced.
+fn replace_getter(current: &mut Getter, callback: Option<Box<dyn FnMut() -> String>>, get: fn() -> JsValue, set: fn(&Function)) {
+    let previous = current.take();
+    let original = match &previous {
+        Some((_, original)) => original.clone(),
+        None => get(),
+    };
+    match callback {
+        Some(callback) => {
+            let closure = Closure::wrap(callback);
+            set(closure.as_ref().unchecked_ref());
+            *current = Some((closure, original));
+        }
+        None => set(original.unchecked_ref()),
+    }
+    drop(previous);
+}
+
+/// Makes the original getter of the Web SDK report routes. Callbacks set in the meantime restore the returned closure
+/// instead, when they are removed.
+fn route_getter(current: &mut Getter, get: fn() -> JsValue, set: fn(&Function)) -> Closure<dyn FnMut() -> String> {
+    let original: Function = match current {
+        Some((_, original)) => original.clone(),
+        None => get(),
+    }.unchecked_into();
+    let routed = Closure::wrap(Box::new(move || {
+        let value = original.call0(&JsValue::UNDEFINED).ok().and_then(|value| value.as_string()).unwrap_or_default();
+        routes::normalize(&value)
+    }) as Box<dyn FnMut() -> String>);
+    match current {
+        Some((_, original)) => *original = routed.as_ref().clone(),
+        None => set(routed.as_ref().unchecked_ref()),
+    }
+    routed
+}
+
+/// Forwards everything to the Countly Web SDK.
+pub(crate) struct WebSdk;
+
+impl WebSdk {
+    fn queue() -> Array {
+        CountlySys::queue()
+    }
+}
+
+impl Backend for WebSdk {
+    fn configure(config: Config) {
+        CountlySys::init(JsValue::from_serde(&config).unwrap());
+        if routes::is_configured() {
+            ROUTED.with(|routed| {
+                let mut routed = routed.borrow_mut();
+                if routed.is_empty() {
+                    routed.push(VIEW_NAME.with(|current| route_getter(&mut current.borrow_mut(), CountlySys::view_name_getter, CountlySys::set_view_name_getter)));
+                    routed.push(VIEW_URL.with(|current| route_getter(&mut current.borrow_mut(), CountlySys::view_url_getter, CountlySys::set_view_url_getter)));
+                }
+            });
+        }
+    }
+
+    fn enable_session_tracking() {
+        Self::queue().push(Array::of1(&JsValue::from_str("track_sessions")).unchecked_ref());
+    }
+
+    fn track_pageview(name: Option<&str>, filter: Option<&[&str]>) {
+        let name = name.map(routes::normalize);
+        match (name.as_deref(), filter) {
+            (None, None) => Self::queue().push(Array::of1(&JsValue::from_str("track_pageview")).unchecked_ref()),
+            (Some(name), None) => Self::queue().push(Array::of2(&JsValue::from_str("track_pageview"), &JsValue::from_str(name)).unchecked_ref()),
+            (None, Some(filter)) => Self::queue().push(Array::of2(&JsValue::from_str("track_pageview"), &JsValue::from_serde(&filter).unwrap()).unchecked_ref()),
+            (Some(name), Some(filter)) => Self::queue().push(Array::of3(&JsValue::from_str("track_pageview"), &JsValue::from_str(name), &JsValue::from_serde(&filter).unwrap()).unchecked_ref()),
+        };
+    }
+
+    fn set_view_name_callback(callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        VIEW_NAME.with(|current| replace_getter(&mut current.borrow_mut(), callback, CountlySys::view_name_getter, CountlySys::set_view_name_getter));
+        Ok(())
+    }
+
+    fn set_view_url_callback(callback: Option<Box<dyn FnMut() -> String>>) -> Result<(), Unsupported> {
+        VIEW_URL.with(|current| replace_getter(&mut current.borrow_mut(), callback, CountlySys::view_url_getter, CountlySys::set_view_url_getter));
+        Ok(())
+    }
+
+    fn enable_auto_view_tracking(view_name: ViewName) -> Result<AutoViewTracking, Unsupported> {
+        AutoViewTracking::start(view_name, |name| Self::track_pageview(Some(name), None))
+            .ok_or(Unsupported::new("Automatic view tracking outside a browser", "web"))
+    }
+
+    fn enable_link_tracking(parent: Option<web_sys::Element>) -> Result<(), Unsupported> {
+        if let Some(parent) = parent {
+            Self::queue().push(Array::of2(&JsValue::from_str("track_links"), parent.unchecked_ref()).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("track_links")).unchecked_ref());
+        }
+        Ok(())
+    }
+
+    fn enable_form_submission_tracking(parent: Option<&web_sys::Element>, include_hidden: bool) -> Result<(), Unsupported> {
+        let null = JsValue::NULL;
+        let parent = if let Some(parent) = parent {
+            parent.unchecked_ref()
+        } else {
+            &null
+        };
+        Self::queue().push(Array::of3(&JsValue::from_str("track_forms"), parent, &JsValue::from_bool(include_hidden)).unchecked_ref());
+        Ok(())
+    }
+
+    fn enable_conversion_reporting(name: Option<&str>) {
+        if let Some(name) = name {
+            Self::queue().push(Array::of2(&JsValue::from_str("report_conversion"), &JsValue::from_str(name)).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("report_conversion")).unchecked_ref());
+        }
+    }
+
+    fn opt_in() {
+        Self::queue().push(Array::of1(&JsValue::from_str("opt_in")).unchecked_ref());
+    }
+
+    fn opt_out() {
+        Self::queue().push(Array::of1(&JsValue::from_str("opt_out")).unchecked_ref());
+    }
+
+    fn enable_form_data_collection(parent: Option<&web_sys::Element>, custom_properties: bool) -> Result<(), Unsupported> {
+        let null = JsValue::NULL;
+        let parent = if let Some(parent) = parent {
+            parent.unchecked_ref()
+        } else {
+            &null
+        };
+        Self::queue().push(Array::of3(&JsValue::from_str("collect_from_forms"), parent, &JsValue::from_bool(custom_properties)).unchecked_ref());
+        Ok(())
+    }
+
+    fn collect_from_facebook(custom_properties: &HashMap<String, String>) -> Result<(), Unsupported> {
+        CountlySys::collect_from_facebook(JsValue::from_serde(&custom_properties).unwrap());
+        Ok(())
+    }
+
+    fn add_event(event: CustomEvent) {
+        Self::queue().push(Array::of2(&JsValue::from_str("add_event"), &JsValue::from_serde(&event).unwrap()).unchecked_ref());
+    }
+
+    fn start_event(name: &str) {
+        Self::queue().push(Array::of2(&JsValue::from_str("start_event"), &JsValue::from_str(name)).unchecked_ref());
+    }
+
+    fn end_event(name: &str) {
+        Self::queue().push(Array::of2(&JsValue::from_str("end_event"), &JsValue::from_str(name)).unchecked_ref());
+    }
+
+    fn set_user_details(details: UserDetails) {
+        Self::queue().push(Array::of2(&JsValue::from_str("user_details"), &JsValue::from_serde(&details).unwrap()).unchecked_ref());
+    }
+
+    fn user_data(key: &str, op: UserDataOp) {
+        let command = JsValue::from_str(match op {
+            UserDataOp::Set(_) => "userData.set",
+            UserDataOp::Unset => "userData.unset",
+            UserDataOp::SetOnce(_) => "userData.set_once",
+            UserDataOp::Increment => "userData.increment",
+            UserDataOp::IncrementBy(_) => "userData.increment_by",
+            UserDataOp::Multiply(_) => "userData.multiply",
+            UserDataOp::Max(_) => "userData.max",
+            UserDataOp::Min(_) => "userData.min",
+            UserDataOp::Push(_) => "userData.push",
+            UserDataOp::PushUnique(_) => "userData.push_unique",
+            UserDataOp::Pull(_) => "userData.pull",
+        });
+        let key = JsValue::from_str(key);
+        let value: Option<JsValue> = match op {
+            UserDataOp::Unset | UserDataOp::Increment => None,
+            UserDataOp::Set(value) | UserDataOp::SetOnce(value) | UserDataOp::Push(value)
+                | UserDataOp::PushUnique(value) | UserDataOp::Pull(value) => Some(value.into()),
+            UserDataOp::IncrementBy(value) | UserDataOp::Multiply(value)
+                | UserDataOp::Max(value) | UserDataOp::Min(value) => Some(Value::Number(value).into()),
+        };
+        if let Some(value) = value {
+            Self::queue().push(Array::of3(&command, &key, &value).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of2(&command, &key).unchecked_ref());
+        }
+    }
+
+    fn user_data_save() {
+        Self::queue().push(Array::of1(&JsValue::from_str("userData.save")).unchecked_ref());
+    }
+
+    fn enable_track_errors(segments: Option<HashMap<String, String>>) {
+        if let Some(segments) = segments {
+            Self::queue().push(Array::of2(&JsValue::from_str("track_errors"), &JsValue::from_serde(&segments).unwrap()).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("track_errors")).unchecked_ref());
+        }
+    }
+
+    fn log_error(error: JsValue, segments: Option<HashMap<String, String>>) {
+        if let Some(segments) = segments {
+            Self::queue().push(Array::of3(&JsValue::from_str("log_error"), &error, &JsValue::from_serde(&segments).unwrap()).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of2(&JsValue::from_str("log_error"), &error).unchecked_ref());
+        }
+    }
+
+    fn add_log(msg: &str) {
+        Self::queue().push(Array::of2(&JsValue::from_str("add_log"), &JsValue::from_str(msg)).unchecked_ref());
+    }
+
+    fn device_id() -> Option<String> {
+        CountlySys::get_device_id().ok()?.as_string()
+    }
+
+    fn device_id_type() -> Option<DeviceIdType> {
+        let code = CountlySys::get_device_id_type().ok()?.as_f64()?;
+        DeviceIdType::from_code(code as u32)
+    }
+
+    fn change_device_id(id: &str, change: DeviceIdChange) {
+        let merge = change == DeviceIdChange::WithMerge;
+        Self::queue().push(Array::of3(&JsValue::from_str("change_id"), &JsValue::from_str(id), &JsValue::from_bool(merge)).unchecked_ref());
+    }
+
+    fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>) {
+        CountlySys::group_features(JsValue::from_serde(&groups).unwrap());
+    }
+
+    fn add_consent(features: &[&str]) {
+        CountlySys::add_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+    }
+
+    fn remove_consent(features: &[&str]) {
+        CountlySys::remove_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+    }
+
+    fn begin_session(no_heart_beat: bool) {
+        if no_heart_beat {
+            Self::queue().push(Array::of2(&JsValue::from_str("begin_session"), &JsValue::TRUE).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("begin_session")).unchecked_ref());
+        }
+    }
+
+    fn extend_session(secs: f64) {
+        Self::queue().push(Array::of2(&JsValue::from_str("session_duration"), &JsValue::from_f64(secs)).unchecked_ref());
+    }
+
+    fn end_session(secs: Option<f64>) {
+        if let Some(secs) = secs {
+            Self::queue().push(Array::of2(&JsValue::from_str("end_session"), &JsValue::from_f64(secs)).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("end_session")).unchecked_ref());
+        }
+    }
+
+    fn enable_offline_mode() {
+        Self::queue().push(Array::of1(&JsValue::from_str("enable_offline_mode")).unchecked_ref());
+    }
+
+    fn disable_offline_mode(device_id: Option<&str>) {
+        if let Some(device_id) = device_id {
+            Self::queue().push(Array::of2(&JsValue::from_str("disable_offline_mode"), &JsValue::from_str(device_id)).unchecked_ref());
+        } else {
+            Self::queue().push(Array::of1(&JsValue::from_str("disable_offline_mode")).unchecked_ref());
+        }
+    }
+
+    fn set_location(location: Location) {
+        // The Web SDK adds these to its requests, it doesn't take coordinates.
+        let value = |value: Option<String>| value.map(|value| JsValue::from_str(&value)).unwrap_or(JsValue::NULL);
+        CountlySys::set_country_code(value(location.country_code));
+        CountlySys::set_city(value(location.city));
+        CountlySys::set_ip_address(value(location.ip_address));
+    }
+
+    fn disable_location() {
+        // Forgets the location it has and sends an empty one, so the server forgets it as well.
+        Self::queue().push(Array::of1(&JsValue::from_str("disable_location")).unchecked_ref());
+    }
+}
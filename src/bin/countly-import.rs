@@ -0,0 +1,82 @@
+//! Imports historical events from JSON Lines or CSV files, see the `countly::import` module.
+
+use std::{env, path::PathBuf, process};
+use countly::{Config, import::{Format, ImportOptions, Importer}};
+
+const USAGE: &str = "\
+Usage: countly-import [OPTIONS] FILE...
+
+Sends the events in FILE (.jsonl or .csv) to a Countly server. Progress is stored in FILE.progress, so an interrupted
+import continues where it stopped when started again.
+
+Options:
+    --url URL           server url (default: $COUNTLY_URL)
+    --app-key KEY       app key (default: $COUNTLY_APP_KEY)
+    --salt SALT         salt for checksums (default: $COUNTLY_SALT)
+    --format FORMAT     jsonl or csv (default: from the file extension)
+    --batch-size N      events per request (default: 100)
+    --retries N         retries of a failed request (default: 5)
+    --no-progress       don't store or use progress files
+    -h, --help          print this help";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("countly-import: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn main() {
+    let mut url = env::var("COUNTLY_URL").ok();
+    let mut app_key = env::var("COUNTLY_APP_KEY").ok();
+    let mut salt = env::var("COUNTLY_SALT").ok();
+    let mut format = None;
+    let mut options = ImportOptions::default();
+    let mut progress = true;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().unwrap_or_else(|| fail(&format!("{} needs a value", name)));
+        match arg.as_str() {
+            "--url" => url = Some(value(&arg)),
+            "--app-key" => app_key = Some(value(&arg)),
+            "--salt" => salt = Some(value(&arg)),
+            "--format" => format = match value(&arg).as_str() {
+                "jsonl" => Some(Format::JsonLines),
+                "csv" => Some(Format::Csv),
+                other => fail(&format!("Unknown format {}", other)),
+            },
+            "--batch-size" => options.batch_size = value(&arg).parse().unwrap_or_else(|_| fail("Invalid batch size")),
+            "--retries" => options.max_retries = value(&arg).parse().unwrap_or_else(|_| fail("Invalid number of retries")),
+            "--no-progress" => progress = false,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => fail(&format!("Unknown option {}", arg)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    let url = url.unwrap_or_else(|| fail("No server url given"));
+    let app_key = app_key.unwrap_or_else(|| fail("No app key given"));
+    if files.is_empty() {
+        fail("No files given");
+    }
+
+    let mut config = Config::new(&app_key, &url);
+    config.salt = salt;
+    for file in files {
+        let mut progress_file = file.clone().into_os_string();
+        progress_file.push(".progress");
+        options.progress_file = if progress { Some(progress_file.into()) } else { None };
+        match Importer::new(&config, options.clone()).import_file(&file, format) {
+            Ok(summary) => println!(
+                "{}: imported {} events in {} requests, skipped {} imported before",
+                file.display(), summary.imported, summary.requests, summary.skipped,
+            ),
+            Err(err) => {
+                eprintln!("{}: {}", file.display(), err);
+                process::exit(1);
+            }
+        }
+    }
+}
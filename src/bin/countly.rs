@@ -0,0 +1,261 @@
+//! Command line tool to talk to a Countly server like an app would, for checking the server setup and debugging.
+
+use std::{collections::HashMap, env, fs, process, time::Duration};
+use serde_json::{Map, Value as Json};
+use countly::{Config, Countly, UserDetails, api::ApiClient};
+
+const USAGE: &str = "\
+Usage: countly [OPTIONS] COMMAND [ARGS]
+
+Commands:
+    event KEY [--count N] [--sum N] [--dur SECS] [--segment NAME=VALUE]...
+                          record a custom event
+    session begin         begin a session
+    session end [SECS]    report a whole session of SECS seconds (default: 0), since sessions aren't kept between runs
+    user NAME=VALUE...    set user properties: name, username, email, organization, phone, picture, gender and byear
+                          are user details, everything else is a custom property
+    remote-config [KEY]...
+                          print the remote config values of the device, needs a device id
+    config                print the configuration
+
+Options:
+    --config FILE         read the configuration from a JSON file with the fields of countly::Config
+                          (default: $COUNTLY_CONFIG)
+    --url URL             server url (default: $COUNTLY_URL)
+    --app-key KEY         app key (default: $COUNTLY_APP_KEY)
+    --device-id ID        device id (default: $COUNTLY_DEVICE_ID, random if not set)
+    --salt SALT           salt for checksums (default: $COUNTLY_SALT)
+    --dry-run             print the requests instead of sending them
+    --timeout SECS        how long to wait for the server (default: 10)
+    -d, --debug           print debug output
+    -h, --help            print this help";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("countly: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn number<T: std::str::FromStr>(value: &str, name: &str) -> T {
+    value.parse().unwrap_or_else(|_| fail(&format!("Invalid {}: {}", name, value)))
+}
+
+fn key_value(arg: &str) -> (String, String) {
+    match arg.find('=') {
+        Some(idx) => (arg[..idx].to_owned(), arg[idx + 1..].to_owned()),
+        None => fail(&format!("Expected NAME=VALUE, got {}", arg)),
+    }
+}
+
+/// The configuration file, overridden by environment variables, overridden by options.
+fn load_config(file: Option<String>, overrides: Map<String, Json>) -> Config {
+    let mut config = match file.or_else(|| env::var("COUNTLY_CONFIG").ok()) {
+        Some(file) => {
+            let json = fs::read_to_string(&file).unwrap_or_else(|err| fail(&format!("Can't read {}: {}", file, err)));
+            match serde_json::from_str(&json) {
+                Ok(Json::Object(config)) => config,
+                _ => fail(&format!("{} doesn't contain a JSON object", file)),
+            }
+        }
+        None => Map::new(),
+    };
+    for (field, var) in &[("url", "COUNTLY_URL"), ("app_key", "COUNTLY_APP_KEY"), ("device_id", "COUNTLY_DEVICE_ID"), ("salt", "COUNTLY_SALT")] {
+        if let Ok(value) = env::var(var) {
+            config.insert((*field).to_owned(), Json::from(value));
+        }
+    }
+    config.extend(overrides);
+    for field in &["url", "app_key"] {
+        if config.get(*field).and_then(Json::as_str).map(str::is_empty).unwrap_or(true) {
+            fail(&format!("No {} given", field));
+        }
+    }
+    serde_json::from_value(Json::Object(config)).unwrap_or_else(|err| fail(&format!("Invalid configuration: {}", err)))
+}
+
+fn event(args: &[String]) {
+    let key = args.first().unwrap_or_else(|| fail("event needs a key"));
+    let mut count = 1;
+    let mut sum = None;
+    let mut duration = None;
+    let mut segmentation = HashMap::new();
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(&format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--count" => count = number(value(), "count"),
+            "--sum" => sum = Some(number(value(), "sum")),
+            "--dur" => duration = Some(number(value(), "duration")),
+            "--segment" => {
+                let (name, value) = key_value(value());
+                segmentation.insert(name, value.into());
+            }
+            _ => fail(&format!("Unknown argument {}", arg)),
+        }
+    }
+    Countly::add_event(key, count, sum, duration, segmentation);
+}
+
+fn session(args: &[String]) {
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("begin"), None) => Countly::begin_session(true),
+        (Some("end"), duration) => {
+            Countly::begin_session(true);
+            Countly::end_session(Some(duration.map(|duration| number(duration, "duration")).unwrap_or(0.0)));
+        }
+        _ => fail("Expected session begin or session end [SECS]"),
+    }
+}
+
+fn user(args: &[String]) {
+    if args.is_empty() {
+        fail("user needs at least one property");
+    }
+    let mut details = UserDetails::default();
+    for arg in args {
+        let (name, value) = key_value(arg);
+        match name.as_str() {
+            "name" => details.name = Some(value),
+            "username" => details.username = Some(value),
+            "email" => details.email = Some(value),
+            "organization" => details.organization = Some(value),
+            "phone" => details.phone = Some(value),
+            "picture" => details.picture = Some(value),
+            "gender" => details.gender = Some(value.as_str().into()),
+            "byear" => details.byear = Some(number(&value, "birth year")),
+            _ => {
+                details.custom.insert(name, value.into());
+            }
+        }
+    }
+    if let Err(err) = Countly::set_user_details(details) {
+        fail(&format!("Invalid user details: {}", err));
+    }
+}
+
+fn remote_config(config: &Config, keys: &[String]) {
+    let device_id = config.device_id.as_deref().unwrap_or_else(|| fail("remote-config needs a device id"));
+    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+    // Remote config is authenticated with the app key, so no API key is needed.
+    let client = ApiClient::new(config, "", "");
+    match client.remote_config(device_id, &keys) {
+        Ok(values) => println!("{}", serde_json::to_string_pretty(&values).unwrap_or_default()),
+        Err(err) => {
+            eprintln!("countly: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Nothing is sent in offline mode, so the requests can be inspected. Nothing is stored either, or a dry run would print
+/// the requests left by earlier runs and leave its own to be sent by the next one.
+fn prepare(config: &mut Config, dry_run: bool) {
+    config.offline_mode = dry_run;
+    if dry_run {
+        config.storage_dir = None;
+    }
+}
+
+fn main() {
+    let mut file = None;
+    let mut overrides = Map::new();
+    let mut dry_run = false;
+    let mut timeout = Duration::from_secs(10);
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let arg = &args[i];
+        let mut value = || {
+            i += 1;
+            args.get(i).cloned().unwrap_or_else(|| fail(&format!("{} needs a value", arg)))
+        };
+        match arg.as_str() {
+            "--config" => file = Some(value()),
+            "--url" => { overrides.insert("url".to_owned(), Json::from(value())); }
+            "--app-key" => { overrides.insert("app_key".to_owned(), Json::from(value())); }
+            "--device-id" => { overrides.insert("device_id".to_owned(), Json::from(value())); }
+            "--salt" => { overrides.insert("salt".to_owned(), Json::from(value())); }
+            "--timeout" => timeout = Duration::from_secs_f64(number(&value(), "timeout")),
+            "--dry-run" => dry_run = true,
+            "-d" | "--debug" => { overrides.insert("debug".to_owned(), Json::from(true)); }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => fail(&format!("Unknown option {}", arg)),
+        }
+        i += 1;
+    }
+    let command = args.get(i).unwrap_or_else(|| fail("No command given"));
+    let args = &args[i + 1..];
+
+    let mut config = load_config(file, overrides);
+    match command.as_str() {
+        "config" => {
+            println!("{}", serde_json::to_string_pretty(&config).unwrap_or_default());
+            return;
+        }
+        "remote-config" => return remote_config(&config, args),
+        _ => {}
+    }
+
+    prepare(&mut config, dry_run);
+    Countly::configure(config);
+    match command.as_str() {
+        "event" => event(args),
+        "session" => session(args),
+        "user" => user(args),
+        _ => fail(&format!("Unknown command {}", command)),
+    }
+
+    if dry_run {
+        for request in Countly::pending_requests() {
+            println!("{}", request);
+        }
+    } else if !Countly::flush(timeout) {
+        eprintln!("countly: Not everything could be sent within {:?}, run with --debug to see why", timeout);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use countly_mock::MockServer;
+    use super::*;
+
+    #[test]
+    fn dry_runs_leave_stored_requests_alone() {
+        let server = MockServer::start();
+        let dir = env::temp_dir().join(format!("countly-cli-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut stored = Config::new("key", server.url());
+        stored.device_id = Some("device".to_owned());
+        stored.storage_dir = Some(dir.clone());
+        let run = |dry_run: bool, key: &str| {
+            let mut config = stored.clone();
+            prepare(&mut config, dry_run);
+            Countly::configure(config);
+            Countly::add_event(key, 1, None, None, HashMap::new());
+        };
+
+        // A run that couldn't reach the server leaves its request on disk.
+        server.fail_all(Some(500));
+        run(false, "stored");
+        Countly::shutdown();
+        server.fail_all(None);
+
+        run(true, "dry");
+        let pending = Countly::pending_requests();
+        assert_eq!(pending.len(), 1);
+        assert!(pending[0].contains("dry") && !pending[0].contains("stored"));
+        Countly::shutdown();
+
+        run(false, "sent");
+        assert!(Countly::flush(Duration::from_secs(5)));
+        Countly::shutdown();
+        server.assert_event("stored");
+        server.assert_event("sent");
+        server.assert_event_count("dry", 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
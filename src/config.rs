@@ -1,12 +1,18 @@
-use std::collections::HashMap;
-use serde::Serialize;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use serde::{Deserialize, Serialize};
+use crate::{device_id::DeviceIdGenerator, routes::Routes};
 
-#[derive(Debug, Clone, Serialize)]
+/// Everything needed to initialize the SDK.
+///
+/// Can also be read from a JSON object with the same field names, for example from a configuration file. Missing fields
+/// get the same defaults as with [Config::new].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default = "Config::empty")]
 pub struct Config {
     /// mandatory, app key for your app created in Countly
-    app_key: String,
+    pub(crate) app_key: String,
     /// your Countly server url - you can also use your own server URL or IP here
-    url: String,
+    pub(crate) url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     /// to identify a visitor, will be auto generated if not provided
     pub device_id: Option<String>,
@@ -96,9 +102,40 @@ pub struct Config {
     /// But there are cases, when you want to keep them completely separate, and for that, you need to provide namespace for
     /// different trackers, so their local storages would not clash.
     pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Salt for parameter tampering protection. If the app on the server requires a checksum, every request carries the
+    /// SHA-256 of its parameters and this salt as `checksum256` (default: none)
+    pub salt: Option<String>,
+    #[serde(skip_serializing)]
+    /// Only used by the `native` feature: directory to keep the device id and the queue of unsent requests in, so they
+    /// survive restarts. Without it, unsent requests are lost when the process exits (default: none)
+    pub storage_dir: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    /// Remember the user details and custom properties sent for the current device id, and leave out values that didn't
+    /// change when they are set again, for example on every login. Forgotten when the device id changes or the process
+    /// (or page) ends (default: false)
+    pub profile_cache: bool,
+    #[serde(skip)]
+    /// Only used by the `pure` and `native` features: creates the device id if neither [Config::device_id] nor a
+    /// stored one is available, see [HashedUserId](crate::HashedUserId). The JavaScript SDKs always generate a UUID v4
+    /// (default: [UuidV4](crate::UuidV4))
+    pub device_id_generator: Option<Arc<dyn DeviceIdGenerator>>,
+    #[serde(skip_serializing)]
+    /// Only used by the `pure` feature: coordinate with the other tabs of the site (in the same namespace). They share one
+    /// session, kept alive by whichever tab currently leads, and follow each other's device id and consent changes
+    /// (default: false)
+    pub cross_tab: bool,
+    #[serde(skip)]
+    /// Report views and links per route of your app instead of per path, see [Routes](crate::Routes) (default: none)
+    pub routes: Option<Routes>,
 }
 
 impl Config {
+    /// Defaults for deserializing, a missing app key or url is left empty.
+    fn empty() -> Self {
+        Self::new("", "")
+    }
+
     pub fn new(app_key: &str, url: &str) -> Self {
         Config {
             app_key: app_key.to_owned(),
@@ -128,6 +165,12 @@ impl Config {
             remote_config: false,
             offline_mode: false,
             namespace: None,
+            salt: None,
+            storage_dir: None,
+            profile_cache: false,
+            device_id_generator: None,
+            cross_tab: false,
+            routes: None,
         }
     }
 }
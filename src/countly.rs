@@ -1,85 +1,111 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 use crate::{
     Config,
-    countly_sys::Countly as CountlySys,
+    backend::{Active, Backend},
+    device_id::{DeviceIdChange, DeviceIdType},
     gdpr::ConsentFeatures,
+    location::Location,
+    profile_cache,
+    routes,
+    user_details::{UserDetails, UserDetailsError},
+    view_callback::{self, ViewCallbackHandle},
+    auto_views::AutoViewTracking,
 };
-use wasm_bindgen::{JsValue, JsCast, closure::Closure};
-use js_sys::Array;
-use serde::Serialize;
+use wasm_bindgen::{JsValue, JsCast};
+use js_sys::{Array, Object, Reflect};
+use serde::{Deserialize, Serialize};
 
 pub struct Countly;
 
+
 impl Countly {
     /// Call this function before anything else.
     pub fn configure(config: Config) {
-        CountlySys::init(JsValue::from_serde(&config).unwrap());
-    }
-
-    fn queue() -> Array {
-        CountlySys::queue()
+        profile_cache::configure(config.profile_cache);
+        routes::configure(config.routes.clone());
+        Active::configure(config);
     }
 
     /// This method will automatically track user sessions, by calling begin extend and end session methods.
     pub fn enable_session_tracking() {
-        Self::queue().push(Array::of1(&JsValue::from_str("track_sessions")).unchecked_ref());
+        Active::enable_session_tracking();
     }
 
     /// This method will track current pageview, by using `location.path` as page name and report it to server.
     pub fn track_pageview() {
-        Self::queue().push(Array::of1(&JsValue::from_str("track_pageview")).unchecked_ref());
+        Active::track_pageview(None, None);
     }
 
     /// For Ajax updated contents and single page web applications, pass page name as a parameter to record new page view.
     pub fn track_pageview_with_name(name: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("track_pageview"), &JsValue::from_str(name)).unchecked_ref());
+        Active::track_pageview(Some(name), None);
     }
 
     /// In some cases you want to ignore some URLs to exclude from tracking, like dynamic URLs including user id in the
     /// URL, or internal URLs, or any other reason. You can do so, by providing another parameter with list of strings
     /// of views to ignore or list of regular expressions to ignore.
     pub fn track_pageview_with_filter(filter: &[&str]) {
-        Self::queue().push(Array::of2(&JsValue::from_str("track_pageview"), &JsValue::from_serde(&filter).unwrap()).unchecked_ref());
+        Active::track_pageview(None, Some(filter));
     }
 
     /// In some cases you want to ignore some URLs to exclude from tracking, like dynamic URLs including user id in the
     /// URL, or internal URLs, or any other reason. You can do so, by providing another parameter with list of strings
     /// of views to ignore or list of regular expressions to ignore.
     pub fn track_pageview_with_name_and_filter(name: &str, filter: &[&str]) {
-        Self::queue().push(Array::of3(&JsValue::from_str("track_pageview"), &JsValue::from_str(name), &JsValue::from_serde(&filter).unwrap()).unchecked_ref());
+        Active::track_pageview(Some(name), Some(filter));
     }
 
     /// There are cases when there is a more complex logic to determining view name and in some cases you need to separate
     /// URL and View naming. So you still have some business logic view names, but you have valid URL underneath them to
     /// view action maps, like clicks and scrolls.
     /// 
-    /// Note that this leaks the closure passed. Since you should only set this once and it should last for the whole session, this should be fine.
-    pub fn set_view_name_callback(callback: impl FnMut() -> String + 'static) {
-        let wrapper = Closure::wrap(Box::new(callback) as Box<dyn FnMut() -> String>);
-        CountlySys::set_view_name_getter(wrapper.as_ref().unchecked_ref());
-        wrapper.forget();
+    /// The callback stays set as long as the returned [ViewCallbackHandle] is alive, dropping it restores the previous
+    /// one.
+    ///
+    /// Fails without a browser, with the `native` and `node` features.
+    pub fn set_view_name_callback(callback: impl FnMut() -> String + 'static) -> Result<ViewCallbackHandle, Unsupported> {
+        view_callback::set_view_name(Box::new(callback))
     }
 
     /// There are cases when there is a more complex logic to determining view name and in some cases you need to separate
     /// URL and View naming. So you still have some business logic view names, but you have valid URL underneath them to
     /// view action maps, like clicks and scrolls.
     /// 
-    /// Note that this leaks the closure passed. Since you should only set this once and it should last for the whole session, this should be fine.
-    pub fn set_view_url_callback(callback: impl FnMut() -> String + 'static) {
-        let wrapper = Closure::wrap(Box::new(callback) as Box<dyn FnMut() -> String>);
-        CountlySys::set_view_url_getter(wrapper.as_ref().unchecked_ref());
-        wrapper.forget();
+    /// The callback stays set as long as the returned [ViewCallbackHandle] is alive, dropping it restores the previous
+    /// one.
+    ///
+    /// The URL is only used for heatmaps, so this fails where they aren't supported: with the `pure`, `native` and
+    /// `node` features.
+    pub fn set_view_url_callback(callback: impl FnMut() -> String + 'static) -> Result<ViewCallbackHandle, Unsupported> {
+        view_callback::set_view_url(Box::new(callback))
+    }
+
+    /// Tracks the views of a single-page app without calling [Countly::track_pageview_with_name] on every route
+    /// change: the current view right away, then one per navigation through `history.pushState`,
+    /// `history.replaceState`, the back and forward buttons or a changed fragment. The previous view ends with its
+    /// duration.
+    ///
+    /// `view_name` gets the path with query and fragment, like `/users/12?tab=orders#top`, and returns the name of the
+    /// view or `None` to not track it. A navigation to the view tracked last is ignored, as are redirects right after a
+    /// navigation.
+    ///
+    /// ```ignore
+    /// let views = Countly::enable_auto_view_tracking(|url| url.split('?').next().map(str::to_owned))?;
+    /// ```
+    ///
+    /// Views are tracked as long as the returned [AutoViewTracking] is alive. Fails without a browser, with the
+    /// `native` and `node` features.
+    pub fn enable_auto_view_tracking(view_name: impl FnMut(&str) -> Option<String> + 'static) -> Result<AutoViewTracking, Unsupported> {
+        Active::enable_auto_view_tracking(Box::new(view_name))
     }
 
     /// This method will track click to specific links and will report with custom events with key linkClick and link's text, id and url as segments.
     ///
     /// By default all links would be tracked for whole page, but you may provide the parent node as a parameter for which to track link clicks.
-    pub fn enable_link_tracking(parent: Option<web_sys::Element>) {
-        if let Some(parent) = parent {
-            Self::queue().push(Array::of2(&JsValue::from_str("track_links"), parent.unchecked_ref()).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("track_links")).unchecked_ref());
-        }
+    ///
+    /// Fails without a browser, with the `native` and `node` features.
+    pub fn enable_link_tracking(parent: Option<web_sys::Element>) -> Result<(), Unsupported> {
+        Active::enable_link_tracking(parent)
     }
 
     /// This method will automatically track form submissions and collect form data and input values in the form and report as Custom Event with formSubmit key
@@ -87,14 +113,10 @@ impl Countly {
     /// By default all forms would be tracked for whole page, but you may provide the parent node as a parameter for which to track forms.
     ///
     /// The second parameter controls whether to collect hidden inputs or not. By default hidden inputs are not collected.
-    pub fn enable_form_submission_tracking(parent: Option<&web_sys::Element>, include_hidden: bool) {
-        let null = JsValue::NULL;
-        let parent = if let Some(parent) = parent {
-            parent.unchecked_ref()
-        } else {
-            &null
-        };
-        Self::queue().push(Array::of3(&JsValue::from_str("track_forms"), parent, &JsValue::from_bool(include_hidden)).unchecked_ref());
+    ///
+    /// Fails without a browser, with the `native` and `node` features.
+    pub fn enable_form_submission_tracking(parent: Option<&web_sys::Element>, include_hidden: bool) -> Result<(), Unsupported> {
+        Active::enable_form_submission_tracking(parent, include_hidden)
     }
 
     /// When using Countly attribution analytics, you can also report conversion to Countly server, like for example when visitor purchased something
@@ -110,18 +132,14 @@ impl Countly {
     ///
     /// Note: that conversion for each user may be reported only once, all other conversions will be ignored for this same user.
     pub fn enable_conversion_reporting(name: Option<&str>) {
-        if let Some(name) = name {
-            Self::queue().push(Array::of2(&JsValue::from_str("report_conversion"), &JsValue::from_str(name)).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("report_conversion")).unchecked_ref());
-        }
+        Active::enable_conversion_reporting(name);
     }
     
     /// Resume tracking after a call to [Countly::opt_out]
     /// 
     /// If you want to achieve opt out by default state, combine these methods with initial setting ignore_visitor on Countly init object.
     pub fn opt_in() {
-        Self::queue().push(Array::of1(&JsValue::from_str("opt_in")).unchecked_ref());
+        Active::opt_in();
     }
     
     /// By default Countly SDK is always opt in, but you can easily disable all tracking by calling opt_out method.
@@ -129,7 +147,7 @@ impl Countly {
     /// 
     /// If you want to achieve opt out by default state, combine these methods with initial setting ignore_visitor on Countly init object.
     pub fn opt_out() {
-        Self::queue().push(Array::of1(&JsValue::from_str("opt_out")).unchecked_ref());
+        Active::opt_out();
     }
 
     /// This method will look into forms filled by your users and will try to gather data like name, email address, username, etc from
@@ -138,21 +156,19 @@ impl Countly {
     /// By default all forms will be checked, but optionally you can provide form element if you want to collect data only from specific
     /// form, or call method multiple times for different forms. Also if you already provide data for users, you would not want to over
     /// write it, so you can provide second parameter as true to indicate that found data should be stored in custom properties.
-    pub fn enable_form_data_collection(parent: Option<&web_sys::Element>, custom_properties: bool) {
-        let null = JsValue::NULL;
-        let parent = if let Some(parent) = parent {
-            parent.unchecked_ref()
-        } else {
-            &null
-        };
-        Self::queue().push(Array::of3(&JsValue::from_str("collect_from_forms"), parent, &JsValue::from_bool(custom_properties)).unchecked_ref());
+    ///
+    /// Only supported by the JavaScript SDK in a browser, fails with the `pure`, `native` and `node` features.
+    pub fn enable_form_data_collection(parent: Option<&web_sys::Element>, custom_properties: bool) -> Result<(), Unsupported> {
+        Active::enable_form_data_collection(parent, custom_properties)
     }
 
     /// If your website uses Facebook Javascript SDK, you can use this helper method to automatically collect user data from their
     /// Facebook account. Just call the method right after Facebook SDK initialization and optionally provide object with custom
     /// properties and graph paths for values where to get them.
-    pub fn collect_from_facebook(custom_properties: &HashMap<String, String>) {
-        CountlySys::collect_from_facebook(JsValue::from_serde(&custom_properties).unwrap());
+    ///
+    /// Only supported by the JavaScript SDK in a browser, fails with the `pure`, `native` and `node` features.
+    pub fn collect_from_facebook(custom_properties: &HashMap<String, String>) -> Result<(), Unsupported> {
+        Active::collect_from_facebook(custom_properties)
     }
 
     /// Custom event is a way to track any custom actions or other data you want to track from your website. You can also provide
@@ -165,89 +181,96 @@ impl Countly {
     /// * sum - sum to report with event
     /// * dur - duration in seconds to report with event
     /// * segmentation - an object with key/value pairs to report with event as segments
-    pub fn add_event(key: &str, count: u32, sum: Option<u32>, duration: Option<f64>, segmentation: HashMap<String, String>) {
-        Self::queue().push(Array::of2(&JsValue::from_str("add_event"), &JsValue::from_serde(&CustomEvent {
+    pub fn add_event(key: &str, count: u32, sum: Option<u32>, duration: Option<f64>, segmentation: HashMap<String, Value>) {
+        Active::add_event(CustomEvent {
             key: key.to_owned(),
             count, sum, duration, segmentation,
-        }).unwrap()).unchecked_ref());
+            ..Default::default()
+        });
     }
 
     /// You can report time or duration with every event by providing dur property of the events object. But if you want, you can
     /// also let Web SDK to track duration of some specific event for you, you can use [Countly::start_event] and [Countly::end_event] methods.
     pub fn start_event(name: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("start_event"), &JsValue::from_str(name)).unchecked_ref());
+        Active::start_event(name);
     }
     
     /// Countly will internally mark the start of event and will wait until you end event with end_event method, setting up
     /// dur property based on how much time has passed since start_event for same event name was called.
     pub fn end_event(name: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("end_event"), &JsValue::from_str(name)).unchecked_ref());
+        Active::end_event(name);
     }
     
     /// If you have any details about the user/visitor, you can provide Countly with that information. This will allow you
     /// track each and specific user on "User Profiles" tab, which is available with Countly Enterprise Edition.
-    pub fn set_user_details(details: UserDetails) {
-        Self::queue().push(Array::of2(&JsValue::from_str("user_details"), &JsValue::from_serde(&details).unwrap()).unchecked_ref());
+    ///
+    /// Nothing is sent if the details don't pass [UserDetails::validate].
+    pub fn set_user_details(details: UserDetails) -> Result<(), UserDetailsError> {
+        details.validate()?;
+        profile_cache::set_user_details(details);
+        Ok(())
     }
     
     /// Set custom property.
     pub fn user_data_set(key: &str, value: Value) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.set"), &JsValue::from_str(&key), &value.into()).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Set(value));
     }
 
     /// Remove custom property.
     pub fn user_data_unset(key: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("userData.unset"), &JsValue::from_str(&key)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Unset);
     }
     
     /// Set custom property only if property does not exist.
     pub fn user_data_set_once(key: &str, value: Value) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.set_once"), &JsValue::from_str(&key), &value.into()).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::SetOnce(value));
     }
     
     /// Increment value in key by one.
     pub fn user_data_increment(key: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("userData.increment"), &JsValue::from_str(&key)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Increment);
     }
     
     /// Increment value in key by provided value.
     pub fn user_data_increment_by(key: &str, value: f64) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.increment_by"), &JsValue::from_str(&key), &JsValue::from_f64(value)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::IncrementBy(value));
     }
     
     /// Multiply value in key by provided value.
     pub fn user_data_multiply(key: &str, value: f64) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.multiply"), &JsValue::from_str(&key), &JsValue::from_f64(value)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Multiply(value));
     }
     
     /// Save max value between current and provided.
     pub fn user_data_max(key: &str, value: f64) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.max"), &JsValue::from_str(&key), &JsValue::from_f64(value)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Max(value));
     }
     
     /// Save min value between current and provided.
     pub fn user_data_min(key: &str, value: f64) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.min"), &JsValue::from_str(&key), &JsValue::from_f64(value)).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Min(value));
     }
     
     /// Add value to key as array element.
     pub fn user_data_push(key: &str, value: Value) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.push"), &JsValue::from_str(&key), &value.into()).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Push(value));
     }
     
     /// Add value to key as array element, but only store unique values in array.
     pub fn user_data_push_unique(key: &str, value: Value) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.push_unique"), &JsValue::from_str(&key), &value.into()).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::PushUnique(value));
     }
     
     /// Remove value from array under property with key as name
     pub fn user_data_pull(key: &str, value: Value) {
-        Self::queue().push(Array::of3(&JsValue::from_str("userData.pull"), &JsValue::from_str(&key), &value.into()).unchecked_ref());
+        profile_cache::user_data(key, UserDataOp::Pull(value));
     }
 
     /// Send userData to server.
+    ///
+    /// To send several modifications together without forgetting this call, use [UserProfileUpdate](crate::UserProfileUpdate).
     pub fn user_data_save() {
-        Self::queue().push(Array::of1(&JsValue::from_str("userData.save")).unchecked_ref());
+        profile_cache::user_data_save();
     }
 
     /// To automatically capture and report Javascript errors on your website, call this function.
@@ -255,28 +278,32 @@ impl Countly {
     /// You can additionally add more segments or properties/values to track with error reports, by providing an object with
     /// key/values to add to error reports.
     pub fn enable_track_errors(segments: Option<HashMap<String, String>>) {
-        if let Some(segments) = segments {
-            Self::queue().push(Array::of2(&JsValue::from_str("track_errors"), &JsValue::from_serde(&segments).unwrap()).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("track_errors")).unchecked_ref());
-        }
+        Active::enable_track_errors(segments);
     }
 
     /// Apart from reporting unhandled errors automatically, you can also report handled exceptions to server too, so you can figure
     /// out how and even if you need to handle them later on. And optionally you can again provide custom segments to be used in the
     /// report (or use the ones provided with track_error method as default ones).
     pub fn log_error(error: JsValue, segments: Option<HashMap<String, String>>) {
-        if let Some(segments) = segments {
-            Self::queue().push(Array::of3(&JsValue::from_str("log_error"), &error, &JsValue::from_serde(&segments).unwrap()).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of2(&JsValue::from_str("log_error"), &error).unchecked_ref());
-        }
+        Active::log_error(error, segments);
     }
     
     /// To better understand what your users did prior to getting an error, you can leave out breadcrumbs through out the code,
     /// on different user actions. This breadcrumb will be then combined in single log and reported to server too.
     pub fn add_log(msg: &str) {
-        Self::queue().push(Array::of2(&JsValue::from_str("add_log"), &JsValue::from_str(msg)).unchecked_ref());
+        Active::add_log(msg);
+    }
+
+    /// The id the SDK currently sends with requests, `None` before [Countly::configure] (or while the Web SDK isn't
+    /// loaded yet with the `loader` feature).
+    pub fn device_id() -> Option<String> {
+        Active::device_id()
+    }
+
+    /// Whether the current device id was provided, generated or is the temporary one of offline mode. `None` before
+    /// [Countly::configure] and with JavaScript SDKs too old to tell.
+    pub fn device_id_type() -> Option<DeviceIdType> {
+        Active::device_id_type()
     }
 
     /// In some cases you may want to change the ID of the user/device that you provided or Countly generated automatically,
@@ -285,16 +312,21 @@ impl Countly {
     /// In some cases, you may also need to change user's device ID in a way, that server will merge data of both user IDs
     /// (existing and new ID you provided) on the server, eg when user used website without authenticating and have recorded
     /// some data, and then authenticated and you want to change ID to your internal id of this user, to keep tracking it across
-    /// multiple devices. To enable this, set `merge` to `true`.
-    pub fn change_device_id(id: &str, merge: bool) {
-        Self::queue().push(Array::of3(&JsValue::from_str("change_id"), &JsValue::from_str(id), &JsValue::from_bool(merge)).unchecked_ref());
+    /// multiple devices. To enable this, pass [DeviceIdChange::WithMerge].
+    ///
+    /// In offline mode, the new id replaces the temporary one and offline mode ends, regardless of `change`.
+    ///
+    /// This also forgets the profile remembered with [Config::profile_cache].
+    pub fn change_device_id(id: &str, change: DeviceIdChange) {
+        profile_cache::clear();
+        Active::change_device_id(id, change);
     }
     
     /// Depending on your website and use case, you may want to combine some of the consent features into one.
     /// 
     /// After this call [Countly::add_consent] to allow this specific combination of features.
     pub fn group_features(groups: HashMap<String, Vec<ConsentFeatures>>) {
-        CountlySys::group_features(JsValue::from_serde(&groups).unwrap());
+        Active::group_features(groups);
     }
 
     /// Upon visitor arriving at your website, you should check if you already have consent from this visitor. If not, you should
@@ -302,43 +334,37 @@ impl Countly {
     /// preferences, you should persistently store it and on each Countly load, let Countly know which features did user consent
     /// to by calling this method and passing one or multiple features.
     pub fn add_consent(features: &[&str]) {
-        CountlySys::add_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+        Active::add_consent(features);
     }
 
     /// You should also allow user to change their mind in, for example, separate settings screen and upon changes made there,
     /// call respective Countly.add_consent or Countly.remove_consent methods, to let Countly track specific features or disable
     /// tracking for them.
     pub fn remove_consent(features: &[&str]) {
-        CountlySys::remove_consent(JsValue::from_serde(features).unwrap().unchecked_into());
+        Active::remove_consent(features);
     }
 
     /// This method would allow you to control sessions manually. Use it only, if you don't call track_sessions method and set
     /// `use_session_cookie` setting to false, for more granular control of the session.
     /// 
     /// If `no_heart_beat` is `true`, then Countly WebSDK won't extend session automatically, and you would need to do that automatically.
+    ///
+    /// [Session](crate::Session) does this and measures the durations for you.
     pub fn begin_session(no_heart_beat: bool) {
-        if no_heart_beat {
-            Self::queue().push(Array::of2(&JsValue::from_str("begin_session"), &JsValue::TRUE).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("begin_session")).unchecked_ref());
-        }
+        Active::begin_session(no_heart_beat);
     }
 
     /// By default (if `no_heart_beat` was false in [Countly::begin_session]) Countly SDK will extend session itself, but if you chose not
     /// to, then you can extend is using this method and provide seconds since last call [Countly::begin_session] or [Countly::extend_session] call,
     /// whatever was the last one.
     pub fn extend_session(secs: f64) {
-        Self::queue().push(Array::of2(&JsValue::from_str("session_duration"), &JsValue::from_f64(secs)).unchecked_ref());
+        Active::extend_session(secs);
     }
 
     /// When visitor is leaving your app or website, you should end his session with this method, optionally providing amount of
     /// seconds since last [Countly::begin_session] or [Countly::extend_session] calls, whatever was the last one.
     pub fn end_session(secs: Option<f64>) {
-        if let Some(secs) = secs {
-            Self::queue().push(Array::of2(&JsValue::from_str("end_session"), &JsValue::from_f64(secs)).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("end_session")).unchecked_ref());
-        }
+        Active::end_session(secs);
     }
 
     /// There are cases, when you want SDK to collect data, but not send it to the server until certain point. Additionally, it
@@ -354,62 +380,217 @@ impl Countly {
     /// providing `device_id` value, if you want.
     /// 
     /// Or you can enable offline at any point later in SDK with this function.
+    ///
+    /// Offline mode is the temporary device id mode: until it ends, [Countly::device_id_type] is
+    /// [DeviceIdType::Temporary] and what is recorded is sent with the id provided when leaving it.
     pub fn enable_offline_mode() {
-        Self::queue().push(Array::of1(&JsValue::from_str("enable_offline_mode")).unchecked_ref());
+        Active::enable_offline_mode();
     }
 
     /// When you want to disable offline mode and optionally provide `device_id`, you can do it with this function.
+    ///
+    /// Without a `device_id`, the SDK goes back to the id it had before.
     pub fn disable_offline_mode(device_id: Option<&str>) {
-        if let Some(device_id) = device_id {
-            Self::queue().push(Array::of2(&JsValue::from_str("disable_offline_mode"), &JsValue::from_str(device_id)).unchecked_ref());
-        } else {
-            Self::queue().push(Array::of1(&JsValue::from_str("disable_offline_mode")).unchecked_ref());
+        if device_id.is_some() {
+            profile_cache::clear();
         }
+        Active::disable_offline_mode(device_id);
+    }
+
+    /// Reports the location of the user, replacing [Config::country_code], [Config::city] and [Config::ip_address].
+    /// Requires consent to [ConsentFeatures::Location] if [Config::require_consent] is set.
+    ///
+    /// The Web SDK doesn't take GPS coordinates and only sends the location with the next session. With the `node`
+    /// feature only the configured location is used.
+    pub fn set_location(location: Location) {
+        Active::set_location(location);
+    }
+
+    /// Stops reporting the location of the user and makes the server forget the location it has, until
+    /// [Countly::set_location] is called again.
+    ///
+    /// With the `node` feature this does nothing, `countly-sdk-nodejs` keeps sending the configured location.
+    pub fn disable_location() {
+        Active::disable_location();
+    }
+}
+
+/// Only available with the `native` feature (and not on wasm, where `pure` takes over if enabled as well), where the
+/// process might exit before the background thread got to send everything.
+#[cfg(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))))]
+impl Countly {
+    /// Sends everything recorded so far and blocks until the server has accepted it or `timeout` has passed. Returns
+    /// whether everything was sent. Use [Countly::flush_async] on a Tokio runtime instead.
+    pub fn flush(timeout: std::time::Duration) -> bool {
+        Active::flush(timeout)
+    }
+
+    /// Stops sending, after waiting for a request that is on its way. Requests that haven't been sent yet are kept in
+    /// [Config::storage_dir] (if set) for the next [Countly::configure], call [Countly::flush] first to send them now.
+    pub fn shutdown() {
+        Active::shutdown()
+    }
+
+    /// The requests that haven't been sent yet, including the recorded events, as URL encoded parameters. Useful with
+    /// `offline_mode` to see what would be sent.
+    pub fn pending_requests() -> Vec<String> {
+        Active::pending_requests()
+    }
+}
+
+/// Only available with the `native-tokio` feature. Requests are sent by a task on the Tokio runtime that
+/// [Countly::configure] is called from (or a thread if there is none), these wait for it without blocking the runtime.
+#[cfg(all(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))), feature = "tokio"))]
+impl Countly {
+    /// Like [Countly::flush], completing once the server has accepted everything recorded so far. While the server
+    /// can't be reached this doesn't complete, use `tokio::time::timeout` to limit the time to wait.
+    pub async fn flush_async() {
+        Active::flush_async().await
+    }
+
+    /// Like [Countly::shutdown], waiting for a request that is on its way without blocking the runtime.
+    pub async fn shutdown_async() {
+        Active::shutdown_async().await
+    }
+}
+
+/// Returned by the functions of [Countly] that need a browser (or the JavaScript SDK), when the enabled features select
+/// an implementation that can't provide them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported {
+    /// What was attempted, like "Link tracking".
+    pub feature: &'static str,
+    /// The cargo feature selecting the implementation, like "node".
+    pub backend: &'static str,
+}
+
+impl Unsupported {
+    #[allow(dead_code)]
+    pub(crate) fn new(feature: &'static str, backend: &'static str) -> Self {
+        Self { feature, backend }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct CustomEvent {
-    key: String,
-    count: u32,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    sum: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    duration: Option<f64>,
-    segmentation: HashMap<String, String>,
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not supported with the `{}` feature", self.feature, self.backend)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct UserDetails {
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub name: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub username: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub email: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub organization: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub phone: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub picture: String,
-    #[serde(skip_serializing_if = "String::is_empty")]
-    pub gender: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub byear: Option<u32>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    pub custom: HashMap<String, String>,
+impl std::error::Error for Unsupported {}
+
+/// A custom event as it is sent to the server, see [Countly::add_event].
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CustomEvent {
+    pub key: String,
+    #[serde(default = "CustomEvent::default_count")]
+    pub count: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum: Option<u32>,
+    #[serde(rename = "dur", default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub segmentation: HashMap<String, Value>,
+    /// Milliseconds since the epoch. Filled in when the event is recorded, the JavaScript SDK does this by itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// 0-23, local time of the user
+    pub hour: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// 0 = Sunday, local time of the user
+    pub dow: Option<u32>,
+}
+
+impl CustomEvent {
+    fn default_count() -> u32 {
+        1
+    }
 }
 
+/// A value of a segment or a custom user property.
+///
+/// Converts from the usual Rust types, so most functions can be called like
+/// `Countly::user_data_set("plan", "pro".into())`. Serializes to the matching JSON value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum Value {
-    Text(String),
+    Null,
+    Bool(bool),
+    Integer(i64),
     Number(f64),
+    Text(String),
     Array(Vec<Value>),
+    Object(HashMap<String, Value>),
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Self::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<HashMap<String, T>> for Value {
+    fn from(values: HashMap<String, T>) -> Self {
+        Self::Object(values.into_iter().map(|(key, value)| (key, value.into())).collect())
+    }
+}
+
+/// `None` becomes [Value::Null].
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
 }
 
 impl Into<JsValue> for Value {
     fn into(self) -> JsValue {
         match self {
+            Self::Null => JsValue::NULL,
+            Self::Bool(b) => JsValue::from_bool(b),
+            Self::Integer(n) => JsValue::from_f64(n as f64),
             Self::Text(s) => JsValue::from_str(&s),
             Self::Number(n) => JsValue::from_f64(n),
             Self::Array(v) => {
@@ -419,6 +600,67 @@ impl Into<JsValue> for Value {
                 }
                 arr.unchecked_into()
             }
+            Self::Object(map) => {
+                let obj = Object::new();
+                for (key, value) in map {
+                    let _ = Reflect::set(&obj, &JsValue::from_str(&key), &value.into());
+                }
+                obj.into()
+            }
         }
     }
 }
+
+/// A single modification of a custom user property, as queued by the `user_data_*` methods of [Countly].
+pub(crate) enum UserDataOp {
+    Set(Value),
+    Unset,
+    SetOnce(Value),
+    Increment,
+    IncrementBy(f64),
+    Multiply(f64),
+    Max(f64),
+    Min(f64),
+    Push(Value),
+    PushUnique(Value),
+    Pull(Value),
+}
+
+impl UserDataOp {
+    /// The name of the corresponding `user_data_*` function, for error messages.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Set(_) => "set",
+            Self::Unset => "unset",
+            Self::SetOnce(_) => "set_once",
+            Self::Increment => "increment",
+            Self::IncrementBy(_) => "increment_by",
+            Self::Multiply(_) => "multiply",
+            Self::Max(_) => "max",
+            Self::Min(_) => "min",
+            Self::Push(_) => "push",
+            Self::PushUnique(_) => "push_unique",
+            Self::Pull(_) => "pull",
+        }
+    }
+
+    /// Whether several of this operation on the same property are collected into an array instead of overriding each other.
+    pub(crate) fn accumulates(&self) -> bool {
+        matches!(self, Self::Push(_) | Self::PushUnique(_) | Self::Pull(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_and_serializes_values() {
+        let mut nested = HashMap::new();
+        nested.insert("enabled".to_owned(), Value::from(true));
+        let value = Value::from(vec![Value::from("a"), 2.into(), 2.5.into(), None::<bool>.into(), nested.into()]);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"["a",2,2.5,null,{"enabled":true}]"#);
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+    }
+}
@@ -1,6 +1,7 @@
 use js_sys::{Function, Array};
 use wasm_bindgen::prelude::*;
 
+#[cfg(not(feature = "loader"))]
 #[wasm_bindgen(module = "countly-sdk-web")]
 extern "C" {
     #[wasm_bindgen(js_name = default)]
@@ -12,12 +13,35 @@ extern "C" {
     #[wasm_bindgen(static_method_of = Countly, js_class = "default", getter = q)]
     pub fn queue() -> Array;
 
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", getter = getViewName)]
+    pub fn view_name_getter() -> JsValue;
+
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", getter = getViewUrl)]
+    pub fn view_url_getter() -> JsValue;
+
     #[wasm_bindgen(static_method_of = Countly, js_class = "default", setter = getViewName)]
     pub fn set_view_name_getter(fun: &Function);
 
     #[wasm_bindgen(static_method_of = Countly, js_class = "default", setter = getViewUrl)]
     pub fn set_view_url_getter(fun: &Function);
 
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", setter = country_code)]
+    pub fn set_country_code(value: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", setter = city)]
+    pub fn set_city(value: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", setter = ip_address)]
+    pub fn set_ip_address(value: JsValue);
+
+    /// Fails while the SDK isn't loaded yet.
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", catch)]
+    pub fn get_device_id() -> Result<JsValue, JsValue>;
+
+    /// One of `Countly.DeviceIdType`, missing in older versions.
+    #[wasm_bindgen(static_method_of = Countly, js_class = "default", catch)]
+    pub fn get_device_id_type() -> Result<JsValue, JsValue>;
+
     #[wasm_bindgen(static_method_of = Countly, js_class = "default")]
     pub fn collect_from_facebook(custom_properties: JsValue);
 
@@ -49,3 +73,187 @@ extern "C" {
     pub fn fetch_remote_config_except_for_keys(null: JsValue, keys: Array, callback: &Function);
 
 }
+
+// With the `loader` feature, the SDK is not imported as a module but injected as a classic script,
+// which exposes itself as `window.Countly`.
+#[cfg(feature = "loader")]
+#[wasm_bindgen]
+extern "C" {
+    pub type Countly;
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn init(config: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly, getter = q)]
+    pub fn queue() -> Array;
+
+    #[wasm_bindgen(static_method_of = Countly, getter = getViewName)]
+    pub fn view_name_getter() -> JsValue;
+
+    #[wasm_bindgen(static_method_of = Countly, getter = getViewUrl)]
+    pub fn view_url_getter() -> JsValue;
+
+    #[wasm_bindgen(static_method_of = Countly, setter = getViewName)]
+    pub fn set_view_name_getter(fun: &Function);
+
+    #[wasm_bindgen(static_method_of = Countly, setter = getViewUrl)]
+    pub fn set_view_url_getter(fun: &Function);
+
+    #[wasm_bindgen(static_method_of = Countly, setter = country_code)]
+    pub fn set_country_code(value: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly, setter = city)]
+    pub fn set_city(value: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly, setter = ip_address)]
+    pub fn set_ip_address(value: JsValue);
+
+    /// Fails while the SDK isn't loaded yet.
+    #[wasm_bindgen(static_method_of = Countly, catch)]
+    pub fn get_device_id() -> Result<JsValue, JsValue>;
+
+    /// One of `Countly.DeviceIdType`, missing in older versions.
+    #[wasm_bindgen(static_method_of = Countly, catch)]
+    pub fn get_device_id_type() -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn collect_from_facebook(custom_properties: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn group_features(groups: JsValue);
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn add_consent(features: Array);
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn remove_consent(features: Array);
+
+    #[wasm_bindgen(static_method_of = Countly, setter = remote_config)]
+    pub fn set_remote_config(callback: &Function);
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn get_remote_config() -> JsValue;
+
+    #[wasm_bindgen(static_method_of = Countly, js_name = get_remote_config)]
+    pub fn get_remote_config_for_key(key: &str) -> JsValue;
+
+    #[wasm_bindgen(static_method_of = Countly)]
+    pub fn fetch_remote_config(callback: &Function);
+
+    #[wasm_bindgen(static_method_of = Countly, js_name = fetch_remote_config)]
+    pub fn fetch_remote_config_for_keys(keys: Array, callback: &Function);
+
+    #[wasm_bindgen(static_method_of = Countly, js_name = fetch_remote_config)]
+    pub fn fetch_remote_config_except_for_keys(null: JsValue, keys: Array, callback: &Function);
+}
+
+/// With the `node` feature, binds to `countly-sdk-nodejs` instead. It is a CommonJS module exporting the SDK object itself,
+/// so the functions are imported one by one rather than as static methods of a class.
+#[cfg(feature = "node")]
+pub mod node {
+    use js_sys::{Array, Function};
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(module = "countly-sdk-nodejs")]
+    extern "C" {
+        pub fn init(config: JsValue);
+
+        pub fn track_sessions();
+
+        pub fn track_view(name: &str);
+
+        pub fn begin_session(no_heart_beat: bool);
+
+        pub fn session_duration(secs: f64);
+
+        pub fn end_session(secs: JsValue);
+
+        pub fn add_event(event: JsValue);
+
+        pub fn start_event(key: &str);
+
+        pub fn end_event(key: &str);
+
+        pub fn report_conversion(campaign_id: &str);
+
+        pub fn user_details(details: JsValue);
+
+        pub fn track_errors(segments: JsValue);
+
+        pub fn log_error(error: JsValue, segments: JsValue);
+
+        pub fn add_log(msg: &str);
+
+        pub fn change_id(id: &str, merge: bool);
+
+        #[wasm_bindgen(catch)]
+        pub fn get_device_id() -> Result<JsValue, JsValue>;
+
+        /// One of `Countly.DeviceIdType`, missing in older versions.
+        #[wasm_bindgen(catch)]
+        pub fn get_device_id_type() -> Result<JsValue, JsValue>;
+
+        pub fn group_features(groups: JsValue);
+
+        pub fn add_consent(features: Array);
+
+        pub fn remove_consent(features: Array);
+
+        pub fn enable_offline_mode();
+
+        pub fn disable_offline_mode(device_id: JsValue);
+
+        pub fn get_remote_config() -> JsValue;
+
+        #[wasm_bindgen(js_name = get_remote_config)]
+        pub fn get_remote_config_for_key(key: &str) -> JsValue;
+
+        pub fn fetch_remote_config(callback: &Function);
+
+        #[wasm_bindgen(js_name = fetch_remote_config)]
+        pub fn fetch_remote_config_for_keys(keys: Array, callback: &Function);
+
+        #[wasm_bindgen(js_name = fetch_remote_config)]
+        pub fn fetch_remote_config_except_for_keys(null: JsValue, keys: Array, callback: &Function);
+    }
+
+    /// `Countly.userData`, which is called directly in Node instead of through the command queue.
+    #[wasm_bindgen(module = "countly-sdk-nodejs")]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = userData, js_name = set)]
+        pub fn user_data_set(key: &str, value: JsValue);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = unset)]
+        pub fn user_data_unset(key: &str);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = set_once)]
+        pub fn user_data_set_once(key: &str, value: JsValue);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = increment)]
+        pub fn user_data_increment(key: &str);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = increment_by)]
+        pub fn user_data_increment_by(key: &str, value: f64);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = multiply)]
+        pub fn user_data_multiply(key: &str, value: f64);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = max)]
+        pub fn user_data_max(key: &str, value: f64);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = min)]
+        pub fn user_data_min(key: &str, value: f64);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = push)]
+        pub fn user_data_push(key: &str, value: JsValue);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = push_unique)]
+        pub fn user_data_push_unique(key: &str, value: JsValue);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = pull)]
+        pub fn user_data_pull(key: &str, value: JsValue);
+
+        #[wasm_bindgen(js_namespace = userData, js_name = save)]
+        pub fn user_data_save();
+    }
+}
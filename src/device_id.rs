@@ -0,0 +1,161 @@
+//! Identifying the device, see [Countly::device_id](crate::Countly::device_id) and
+//! [Config::device_id_generator](crate::Config::device_id_generator).
+
+use std::fmt;
+#[cfg(any(feature = "pure", feature = "native"))]
+use sha2::{Digest, Sha256};
+
+/// Where the current device id comes from, the same distinction the JavaScript SDKs make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceIdType {
+    /// Set with [Config::device_id](crate::Config::device_id),
+    /// [Countly::change_device_id](crate::Countly::change_device_id) or when leaving offline mode.
+    DeveloperSupplied,
+    /// Generated on the first start, see [Config::device_id_generator](crate::Config::device_id_generator).
+    SdkGenerated,
+    /// A placeholder while in offline mode, requests are held until a real id is provided.
+    Temporary,
+}
+
+impl DeviceIdType {
+    /// The value of `Countly.DeviceIdType` in the JavaScript SDKs. They also have an id taken from the URL, which is
+    /// supplied by the developer as well.
+    pub(crate) fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 | 3 => Some(Self::DeveloperSupplied),
+            1 => Some(Self::SdkGenerated),
+            2 => Some(Self::Temporary),
+            _ => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn code(self) -> u32 {
+        match self {
+            Self::DeveloperSupplied => 0,
+            Self::SdkGenerated => 1,
+            Self::Temporary => 2,
+        }
+    }
+}
+
+/// How [Countly::change_device_id](crate::Countly::change_device_id) treats the data recorded for the old id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceIdChange {
+    /// The server merges the old user into the new one, for example when an anonymous visitor logs in.
+    WithMerge,
+    /// The old user is kept separately and the session is restarted for the new one, for example when someone else
+    /// logs in.
+    WithoutMerge,
+}
+
+/// Creates the device id for a device that doesn't have one yet.
+pub trait DeviceIdGenerator: fmt::Debug + Send + Sync {
+    fn generate(&self) -> String;
+}
+
+/// A random UUID v4, which the JavaScript SDKs use as well. The default. Only with the `pure` and `native` features.
+#[cfg(any(feature = "pure", feature = "native"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidV4;
+
+#[cfg(any(feature = "pure", feature = "native"))]
+impl DeviceIdGenerator for UuidV4 {
+    fn generate(&self) -> String {
+        let mut bytes = random_bytes();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+}
+
+/// The SHA-256 of a user id from your own system, so the same user gets the same device id everywhere without sending
+/// the user id itself. Only with the `pure` and `native` features.
+#[cfg(any(feature = "pure", feature = "native"))]
+#[derive(Debug, Clone)]
+pub struct HashedUserId {
+    user_id: String,
+    salt: String,
+}
+
+#[cfg(any(feature = "pure", feature = "native"))]
+impl HashedUserId {
+    pub fn new(user_id: &str) -> Self {
+        Self { user_id: user_id.to_owned(), salt: String::new() }
+    }
+
+    /// Prepended to the user id before hashing, so the ids can't be matched against hashes of known user ids.
+    pub fn with_salt(mut self, salt: &str) -> Self {
+        self.salt = salt.to_owned();
+        self
+    }
+}
+
+#[cfg(any(feature = "pure", feature = "native"))]
+impl DeviceIdGenerator for HashedUserId {
+    fn generate(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(self.user_id.as_bytes());
+        hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// From `crypto.getRandomValues`, which browsers and Node.js both have, falling back to `Math.random`.
+#[cfg(all(any(feature = "pure", feature = "native"), target_arch = "wasm32"))]
+fn random_bytes() -> [u8; 16] {
+    use js_sys::{Function, Reflect, Uint8Array};
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let mut bytes = [0u8; 16];
+    let array = Uint8Array::new_with_length(16);
+    let crypto = Reflect::get(&js_sys::global(), &JsValue::from_str("crypto")).unwrap_or(JsValue::UNDEFINED);
+    let filled = Reflect::get(&crypto, &JsValue::from_str("getRandomValues"))
+        .ok()
+        .and_then(|fun| fun.dyn_into::<Function>().ok())
+        .map(|fun| fun.call1(&crypto, &array).is_ok())
+        .unwrap_or(false);
+    if filled {
+        array.copy_to(&mut bytes);
+    } else {
+        bytes.iter_mut().for_each(|byte| *byte = (js_sys::Math::random() * 256.0) as u8);
+    }
+    bytes
+}
+
+#[cfg(all(any(feature = "pure", feature = "native"), not(target_arch = "wasm32")))]
+fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("no random numbers available");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "pure", feature = "native"))]
+    #[test]
+    fn generates_ids() {
+        let id = UuidV4.generate();
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.split('-').map(str::len).collect::<Vec<_>>(), [8, 4, 4, 4, 12]);
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]));
+        assert_ne!(id, UuidV4.generate());
+
+        let hashed = HashedUserId::new("jane").generate();
+        assert_eq!(hashed, HashedUserId::new("jane").generate());
+        assert_eq!(hashed.len(), 64);
+        assert_ne!(hashed, HashedUserId::new("jane").with_salt("pepper").generate());
+        assert_ne!(hashed, HashedUserId::new("joe").generate());
+    }
+
+    #[test]
+    fn converts_device_id_types() {
+        for device_id_type in [DeviceIdType::DeveloperSupplied, DeviceIdType::SdkGenerated, DeviceIdType::Temporary] {
+            assert_eq!(DeviceIdType::from_code(device_id_type.code()), Some(device_id_type));
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Sending historical events to the server, for example to backfill analytics from logs.
+//!
+//! Events are sent in batches through the `/i/bulk` endpoint. Failed batches are retried, and with
+//! [ImportOptions::progress_file] an interrupted import continues where it stopped when started again.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use countly::{Config, import::{Importer, ImportOptions}};
+//!
+//! let mut options = ImportOptions::default();
+//! options.progress_file = Some("events.jsonl.progress".into());
+//! let importer = Importer::new(&Config::new("APP_KEY", "https://countly.example.com"), options);
+//! let summary = importer.import_file(Path::new("events.jsonl"), None)?;
+//! println!("{} events imported", summary.imported);
+//! # Ok::<(), countly::import::ImportError>(())
+//! ```
+
+mod reader;
+pub use reader::{Format, ImportEvent, read_events};
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+use ureq::Agent;
+use crate::{
+    Config,
+    CustomEvent,
+    protocol::{Now, request::{Request, Server}},
+};
+
+/// Everything that can go wrong during an import.
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    /// A record of the input couldn't be read, counting from 1.
+    Parse { record: u64, msg: String },
+    /// The format couldn't be determined from the file name.
+    UnknownFormat(PathBuf),
+    /// The server rejected a batch with this status and message.
+    Status(u16, String),
+    /// The server couldn't be reached, even after retrying.
+    Transport(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Parse { record, msg } => write!(f, "Record {}: {}", record, msg),
+            Self::UnknownFormat(path) => write!(f, "Unknown format of {}, expected .jsonl or .csv", path.display()),
+            Self::Status(status, msg) => write!(f, "Server returned {}: {}", status, msg),
+            Self::Transport(msg) => write!(f, "Server not reachable: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// maximum amount of events to send in one request (default: 100)
+    pub batch_size: usize,
+    /// how often to retry a failed request before giving up (default: 5)
+    pub max_retries: u32,
+    /// time to wait before the first retry, doubling with every further one (default: 1 second)
+    pub retry_delay: Duration,
+    /// file to store the number of imported records in. If it exists, that many records are skipped, so an interrupted
+    /// import can simply be started again (default: none)
+    pub progress_file: Option<PathBuf>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { batch_size: 100, max_retries: 5, retry_delay: Duration::from_secs(1), progress_file: None }
+    }
+}
+
+/// Result of a successful import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Events sent to the server.
+    pub imported: u64,
+    /// Events skipped because a previous run already imported them.
+    pub skipped: u64,
+    /// Number of `/i/bulk` requests sent.
+    pub requests: u64,
+}
+
+/// See the module documentation.
+pub struct Importer {
+    app_key: String,
+    server: Server,
+    options: ImportOptions,
+    agent: Agent,
+}
+
+impl Importer {
+    /// Uses the app key, server url and salt of `config`.
+    pub fn new(config: &Config, options: ImportOptions) -> Self {
+        Self {
+            app_key: config.app_key.clone(),
+            server: Server::new(config),
+            options,
+            agent: Agent::config_builder().http_status_as_error(false).build().into(),
+        }
+    }
+
+    /// Imports a file, guessing its format from the extension unless given.
+    pub fn import_file(&self, path: &Path, format: Option<Format>) -> Result<ImportSummary, ImportError> {
+        let format = format.or_else(|| Format::from_path(path)).ok_or_else(|| ImportError::UnknownFormat(path.to_owned()))?;
+        self.import(read_events(fs::File::open(path)?, format))
+    }
+
+    /// Imports events in order, stopping at the first one that can't be read or sent.
+    ///
+    /// Progress is stored after every batch, so everything before the batch containing the error has been imported.
+    pub fn import(&self, events: impl Iterator<Item = Result<ImportEvent, ImportError>>) -> Result<ImportSummary, ImportError> {
+        let mut summary = ImportSummary { skipped: self.load_progress()?, ..Default::default() };
+        let mut batch = Vec::with_capacity(self.options.batch_size);
+        for event in events.skip(summary.skipped as usize) {
+            batch.push(event?);
+            if batch.len() >= self.options.batch_size.max(1) {
+                self.send_batch(&mut batch, &mut summary)?;
+            }
+        }
+        if !batch.is_empty() {
+            self.send_batch(&mut batch, &mut summary)?;
+        }
+        Ok(summary)
+    }
+
+    fn send_batch(&self, batch: &mut Vec<ImportEvent>, summary: &mut ImportSummary) -> Result<(), ImportError> {
+        self.send(&self.bulk_request(batch))?;
+        summary.imported += batch.len() as u64;
+        summary.requests += 1;
+        batch.clear();
+        self.store_progress(summary.skipped + summary.imported)
+    }
+
+    /// One request per device, containing its consecutive events.
+    fn bulk_request(&self, batch: &[ImportEvent]) -> Request {
+        let mut requests = Vec::new();
+        let mut events: Vec<CustomEvent> = Vec::new();
+        for (i, import) in batch.iter().enumerate() {
+            let now = Now::utc(import.event.timestamp.unwrap_or_default());
+            let mut event = import.event.clone();
+            event.hour = event.hour.or(Some(now.hour));
+            event.dow = event.dow.or(Some(now.dow));
+            events.push(event);
+            if batch.get(i + 1).map(|next| next.device_id != import.device_id).unwrap_or(true) {
+                let first = Now::utc(events[0].timestamp.unwrap_or_default());
+                let mut request = Request::new(&self.app_key, &import.device_id, first);
+                request.set_json("events", &events);
+                requests.push(request);
+                events.clear();
+            }
+        }
+        Request::bulk(&self.app_key, &requests)
+    }
+
+    /// Sends a request, retrying when the server isn't reachable or temporarily fails.
+    fn send(&self, request: &Request) -> Result<(), ImportError> {
+        let body = self.server.encode(request);
+        let mut delay = self.options.retry_delay;
+        let mut attempt = 0;
+        loop {
+            let result = self.agent.post(&self.server.bulk_endpoint())
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .send(&body);
+            let err = match result {
+                Ok(mut response) => {
+                    let status = response.status().as_u16();
+                    if status < 400 {
+                        return Ok(());
+                    }
+                    let msg = response.body_mut().read_to_string().unwrap_or_default();
+                    ImportError::Status(status, msg)
+                }
+                Err(err) => ImportError::Transport(err.to_string()),
+            };
+            // Other client errors, like a wrong checksum, won't go away by retrying.
+            let retry = match err {
+                ImportError::Status(status, _) => status == 429 || status >= 500,
+                _ => true,
+            };
+            if !retry || attempt >= self.options.max_retries {
+                return Err(err);
+            }
+            attempt += 1;
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+
+    fn load_progress(&self) -> Result<u64, ImportError> {
+        let path = match &self.options.progress_file {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+        match fs::read_to_string(path) {
+            Ok(progress) => progress.trim().parse().map_err(|_| {
+                ImportError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid progress file {}", path.display())))
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn store_progress(&self, done: u64) -> Result<(), ImportError> {
+        if let Some(path) = &self.options.progress_file {
+            // Replace atomically, a torn progress file would make the next run import everything again.
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, done.to_string())?;
+            fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use countly_mock::MockServer;
+    use crate::Value;
+    use super::*;
+
+    const EVENTS: &str = r#"{"device_id": "a", "key": "purchase", "sum": 5, "timestamp": 1577836800000, "segmentation": {"plan": "pro"}}
+{"device_id": "a", "key": "login", "timestamp": 1577836860000}
+
+{"device_id": "b", "key": "login", "count": 2, "dur": 1.5, "timestamp": 1577836920000}
+{"device_id": "b", "key": "logout", "timestamp": 1577836980000}
+{"device_id": "c", "key": "login", "timestamp": 1577837040000}
+"#;
+
+    #[test]
+    fn imports_in_batches_and_resumes() {
+        let server = MockServer::start();
+        server.require_checksum("salt");
+        server.fail_next(1, 503);
+        let mut config = Config::new("key", server.url());
+        config.salt = Some("salt".to_owned());
+        let progress = std::env::temp_dir().join(format!("countly-import-{}.progress", std::process::id()));
+        let _ = fs::remove_file(&progress);
+        let options = ImportOptions { batch_size: 3, retry_delay: Duration::from_millis(1), progress_file: Some(progress.clone()), ..Default::default() };
+        let importer = Importer::new(&config, options);
+
+        // Stops at the broken record, after importing the batches before it.
+        let broken = format!("{}{}", EVENTS, r#"{"device_id": "c", "key": "broken"}"#);
+        match importer.import(read_events(broken.as_bytes(), Format::JsonLines)) {
+            Err(ImportError::Parse { record: 6, msg }) => assert_eq!(msg, "missing timestamp"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(fs::read_to_string(&progress).unwrap(), "3");
+        assert_eq!(server.rejected(), 1);
+
+        let summary = importer.import(read_events(EVENTS.as_bytes(), Format::JsonLines)).unwrap();
+        assert_eq!(summary, ImportSummary { imported: 2, skipped: 3, requests: 1 });
+        fs::remove_file(&progress).unwrap();
+
+        let events = server.events();
+        assert_eq!(events.iter().map(|event| (event.device_id.as_str(), event.key.as_str())).collect::<Vec<_>>(), vec![
+            ("a", "purchase"), ("a", "login"), ("b", "login"), ("b", "logout"), ("c", "login"),
+        ]);
+        let purchase = server.assert_event("purchase");
+        assert_eq!(purchase.sum, Some(5.0));
+        assert_eq!(purchase.timestamp, Some(1577836800000));
+        assert_eq!(purchase.segmentation["plan"], "pro");
+        // One request per device in each batch.
+        assert_eq!(server.requests().iter().map(|request| request.device_id()).collect::<Vec<_>>(), vec!["a", "b", "b", "c"]);
+    }
+
+    #[test]
+    fn reads_csv() {
+        let csv = "device_id,key,count,timestamp,segmentation,plan\n\
+                   a,purchase,2,1577836800000,\"{\"\"source\"\": \"\"ad\"\"}\",pro\n\
+                   b,login,,1577836860000,,\n";
+        let events: Vec<ImportEvent> = read_events(csv.as_bytes(), Format::Csv).collect::<Result<_, _>>().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].device_id, "a");
+        assert_eq!(events[0].event.count, 2);
+        assert_eq!(events[0].event.segmentation["source"], Value::from("ad"));
+        assert_eq!(events[0].event.segmentation["plan"], Value::from("pro"));
+        assert_eq!(events[1].event.count, 1);
+        assert!(events[1].event.segmentation.is_empty());
+    }
+}
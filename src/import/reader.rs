@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+use serde::Deserialize;
+use crate::{CustomEvent, Value};
+use super::ImportError;
+
+/// File formats [read_events] understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line, with the fields of [ImportEvent].
+    JsonLines,
+    /// A header line naming the columns `device_id`, `key`, `timestamp` and optionally `count`, `sum`, `dur` and
+    /// `segmentation` (a JSON object). Every other column is used as a segment, empty cells are left out.
+    Csv,
+}
+
+impl Format {
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "jsonl" | "ndjson" | "json" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// A historical event of a device.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportEvent {
+    pub device_id: String,
+    /// The timestamp is mandatory, everything else is filled in like for [Countly::add_event](crate::Countly::add_event).
+    #[serde(flatten)]
+    pub event: CustomEvent,
+}
+
+impl ImportEvent {
+    fn validate(self, record: u64) -> Result<Self, ImportError> {
+        let msg = if self.device_id.is_empty() {
+            "missing device_id"
+        } else if self.event.key.is_empty() {
+            "missing key"
+        } else if self.event.timestamp.is_none() {
+            "missing timestamp"
+        } else {
+            return Ok(self);
+        };
+        Err(ImportError::Parse { record, msg: msg.to_owned() })
+    }
+}
+
+/// Reads events from `reader`, numbering them from 1 in errors. Empty lines are skipped.
+pub fn read_events<'a>(reader: impl Read + 'a, format: Format) -> Box<dyn Iterator<Item = Result<ImportEvent, ImportError>> + 'a> {
+    match format {
+        Format::JsonLines => Box::new(read_json_lines(reader)),
+        Format::Csv => read_csv(reader),
+    }
+}
+
+fn read_json_lines(reader: impl Read) -> impl Iterator<Item = Result<ImportEvent, ImportError>> {
+    BufReader::new(reader).lines()
+        .filter(|line| line.as_ref().map(|line| !line.trim().is_empty()).unwrap_or(true))
+        .zip(1..)
+        .map(|(line, record)| {
+            let event: ImportEvent = serde_json::from_str(&line?)
+                .map_err(|err| ImportError::Parse { record, msg: err.to_string() })?;
+            event.validate(record)
+        })
+}
+
+fn read_csv<'a>(reader: impl Read + 'a) -> Box<dyn Iterator<Item = Result<ImportEvent, ImportError>> + 'a> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(reader);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(err) => return Box::new(std::iter::once(Err(ImportError::Parse { record: 0, msg: err.to_string() }))),
+    };
+    Box::new(reader.into_records().zip(1..).map(move |(row, record)| {
+        let row = row.map_err(|err| ImportError::Parse { record, msg: err.to_string() })?;
+        parse_row(&headers, &row, record)
+    }))
+}
+
+fn parse_row(headers: &csv::StringRecord, row: &csv::StringRecord, record: u64) -> Result<ImportEvent, ImportError> {
+    fn number<T: std::str::FromStr>(value: &str, column: &str, record: u64) -> Result<T, ImportError> {
+        value.parse().map_err(|_| ImportError::Parse { record, msg: format!("invalid {}: {:?}", column, value) })
+    }
+
+    let mut device_id = String::new();
+    let mut event = CustomEvent { count: 1, ..Default::default() };
+    for (column, value) in headers.iter().zip(row.iter()) {
+        if value.is_empty() {
+            continue;
+        }
+        match column {
+            "device_id" => device_id = value.to_owned(),
+            "key" => event.key = value.to_owned(),
+            "count" => event.count = number(value, column, record)?,
+            "sum" => event.sum = Some(number(value, column, record)?),
+            "dur" => event.duration = Some(number(value, column, record)?),
+            "timestamp" => event.timestamp = Some(number(value, column, record)?),
+            "hour" => event.hour = Some(number(value, column, record)?),
+            "dow" => event.dow = Some(number(value, column, record)?),
+            "segmentation" => {
+                let segments: HashMap<String, Value> = serde_json::from_str(value)
+                    .map_err(|err| ImportError::Parse { record, msg: format!("invalid segmentation: {}", err) })?;
+                event.segmentation.extend(segments);
+            }
+            _ => {
+                event.segmentation.insert(column.to_owned(), value.into());
+            }
+        }
+    }
+    ImportEvent { device_id, event }.validate(record)
+}
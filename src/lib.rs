@@ -19,18 +19,86 @@
 //! Note that you need to add the countly JavaScript SDK as a module to wasm-bindgen (via webpack or a similar module management
 //! system). If you're using npm, you can add it using
 //! 
-//! ```
+//! ```text
 //! npm install --save countly-sdk-web
 //! ```
 //! 
+//! If you don't use a bundler (for example with `wasm-pack build --target web` or Trunk), enable the `loader` feature instead.
+//! The crate then binds to the global `window.Countly` and you have to inject the SDK before configuring it:
+//!
+//! ```ignore
+//! countly::loader::load_sdk(countly::loader::SdkSource::Url("/countly.min.js".to_owned()), 5000).await?;
+//! countly::Countly::configure(countly::Config::new("APP_KEY", "https://countly.example.com"));
+//! ```
+//!
+//! Alternatively, the `pure` feature implements the tracking in Rust directly on top of `web-sys`, so the JavaScript SDK
+//! isn't needed at all. It uses the same [Config] and [Countly] API, but only supports sessions, views, events, user details,
+//! crash reports, link and form tracking, consent and offline mode. Collecting user data from forms or Facebook and heatmaps
+//! return [Unsupported], and view filters only match exactly. Sessions and timed events pause while the page is hidden,
+//! the session ends after [Config::inactivity_time] and a new one begins when the page is shown again. With
+//! [Config::cross_tab], the tabs of a site share one session and follow each other's device id and consent changes.
+//!
+//! Single-page apps can have their views tracked from their navigations with [Countly::enable_auto_view_tracking], and
+//! [Config::routes] reports views and links per route (like `/users/:id`) instead of per path.
+//!
+//! For native (non-browser) targets, the `native` feature provides the same implementation sending requests from a
+//! background thread. It can be enabled together with `pure`, which is then only used for wasm. Set
+//! [Config::storage_dir] to keep unsent requests on disk, they are sent after the next [Countly::configure]. Before
+//! exiting, `Countly::flush` sends what is left and `Countly::shutdown` stops sending. With `native-tokio` instead,
+//! requests are sent from a task on the Tokio runtime, which `Countly::flush_async` and `Countly::shutdown_async` wait
+//! for without blocking it.
+//!
+//! For wasm running under Node.js (server side rendering, serverless functions), the `node` feature binds to
+//! `countly-sdk-nodejs` instead of the Web SDK. Everything that needs a page, like link and form tracking, returns
+//! [Unsupported] there.
+//!
+//! The `api` feature adds the `api` module with a client to query the collected data from the server (for example to build
+//! dashboards) and to manage apps, events and remote config. The `import` feature sends historical events from JSON Lines
+//! or CSV files, as a library and as the `countly-import` binary. The `cli` feature builds the `countly` binary, which
+//! sends test events, sessions and user properties and fetches remote config, to check a server setup from the command line.
+//!
 //! If there is any confusion about the usage, take a look at [the JavaScript SDK documentation](https://support.count.ly/hc/en-us/articles/360037441932-Web-analytics-JavaScript-). This crate is just wrapping the functionality with little changes (except having some type safety).
 
 pub mod countly_sys;
+#[cfg(feature = "loader")]
+pub mod loader;
+#[cfg(feature = "api")]
+pub mod api;
+#[cfg(feature = "import")]
+pub mod import;
 mod config;
 pub use config::Config;
 
+mod backend;
+#[cfg(any(feature = "pure", feature = "native", feature = "import"))]
+mod protocol;
 mod countly;
-pub use countly::{Countly, Value, UserDetails};
+pub use countly::{Countly, CustomEvent, Unsupported, Value};
+
+mod user_details;
+pub use user_details::{Gender, UserDetails, UserDetailsBuilder, UserDetailsError};
 
 mod gdpr;
-pub use gdpr::ConsentFeatures;
\ No newline at end of file
+pub use gdpr::ConsentFeatures;
+
+mod location;
+pub use location::Location;
+
+mod device_id;
+pub use device_id::{DeviceIdChange, DeviceIdGenerator, DeviceIdType};
+#[cfg(any(feature = "pure", feature = "native"))]
+pub use device_id::{HashedUserId, UuidV4};
+
+mod profile_cache;
+mod session;
+pub use session::Session;
+mod user_profile;
+pub use user_profile::{UserProfileError, UserProfileUpdate};
+mod view_callback;
+pub use view_callback::ViewCallbackHandle;
+mod auto_views;
+pub use auto_views::AutoViewTracking;
+mod routes;
+#[cfg(feature = "regex")]
+pub use routes::RouteError;
+pub use routes::Routes;
\ No newline at end of file
@@ -0,0 +1,115 @@
+//! Runtime loading of the Countly Web SDK for setups without a bundler (like `wasm-pack --target web` or Trunk).
+//!
+//! Call [load_sdk] and wait for it to finish before calling [Countly::configure](crate::Countly::configure).
+
+use std::fmt;
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{JsValue, JsCast};
+use wasm_bindgen_futures::JsFuture;
+
+/// How often to check whether `window.Countly` appeared after the script was injected, in milliseconds.
+const POLL_INTERVAL: i32 = 50;
+
+/// Where to get the Web SDK from.
+#[derive(Debug, Clone)]
+pub enum SdkSource {
+    /// Load the script from this URL, for example `https://cdn.jsdelivr.net/npm/countly-sdk-web@latest/lib/countly.min.js`
+    /// or a copy served from your own origin.
+    Url(String),
+    /// Execute the script source directly, for example `include_str!("countly.min.js")` to embed a copy into the binary.
+    Embedded(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    /// There is no `window` or `document` in this environment.
+    NoDocument,
+    /// The browser failed to fetch or execute the script at this URL.
+    ScriptFailed(String),
+    /// The script was loaded, but `window.Countly` did not show up in time.
+    Timeout,
+    /// A JavaScript exception was thrown while injecting the script.
+    Js(JsValue),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoDocument => write!(f, "no document available to inject the Countly SDK into"),
+            Self::ScriptFailed(url) => write!(f, "failed to load the Countly SDK from {}", url),
+            Self::Timeout => write!(f, "timed out waiting for window.Countly"),
+            Self::Js(err) => write!(f, "JavaScript error while loading the Countly SDK: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<JsValue> for LoadError {
+    fn from(err: JsValue) -> Self {
+        Self::Js(err)
+    }
+}
+
+/// Returns true if `window.Countly` is defined.
+pub fn is_loaded() -> bool {
+    Reflect::get(&js_sys::global(), &JsValue::from_str("Countly"))
+        .map(|countly| !countly.is_undefined() && !countly.is_null())
+        .unwrap_or(false)
+}
+
+/// Injects the Web SDK into the current document and waits until `window.Countly` is available.
+///
+/// `timeout_ms` limits how long to wait for `window.Countly` after the script has been executed. If the SDK is already
+/// present (for example because the page includes it via a `<script>` tag), nothing is injected.
+pub async fn load_sdk(source: SdkSource, timeout_ms: u32) -> Result<(), LoadError> {
+    if is_loaded() {
+        return Ok(());
+    }
+    let window = web_sys::window().ok_or(LoadError::NoDocument)?;
+    let document = window.document().ok_or(LoadError::NoDocument)?;
+    let head = document.head().ok_or(LoadError::NoDocument)?;
+    let script: web_sys::HtmlScriptElement = document.create_element("script")?.unchecked_into();
+
+    match source {
+        SdkSource::Url(url) => {
+            let loaded = Promise::new(&mut |resolve: Function, reject: Function| {
+                script.set_onload(Some(&resolve));
+                script.set_onerror(Some(&reject));
+            });
+            script.set_src(&url);
+            head.append_child(&script)?;
+            let result = JsFuture::from(loaded).await;
+            script.set_onload(None);
+            script.set_onerror(None);
+            if result.is_err() {
+                return Err(LoadError::ScriptFailed(url));
+            }
+        }
+        SdkSource::Embedded(code) => {
+            // Inline scripts are executed synchronously on insertion.
+            script.set_text(code)?;
+            head.append_child(&script)?;
+        }
+    }
+
+    let mut waited = 0;
+    while !is_loaded() {
+        if waited >= timeout_ms {
+            return Err(LoadError::Timeout);
+        }
+        sleep(&window, POLL_INTERVAL).await?;
+        waited += POLL_INTERVAL as u32;
+    }
+    Ok(())
+}
+
+async fn sleep(window: &web_sys::Window, ms: i32) -> Result<(), LoadError> {
+    let mut result = Ok(0);
+    let timer = Promise::new(&mut |resolve: Function, _reject: Function| {
+        result = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms);
+    });
+    result?;
+    JsFuture::from(timer).await?;
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! The location reported with [Countly::set_location](crate::Countly::set_location).
+
+/// Where the user is. Fields that are `None` are left for the server to determine from the IP address of the request.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Location {
+    /// ISO 3166-1 alpha-2 code, like `AT`
+    pub country_code: Option<String>,
+    pub city: Option<String>,
+    /// Latitude and longitude in degrees
+    pub gps: Option<(f64, f64)>,
+    /// Used instead of the address the request comes from
+    pub ip_address: Option<String>,
+}
+
+impl Location {
+    /// Only the country, for example as selected by the user.
+    pub fn country(country_code: &str) -> Self {
+        Self { country_code: Some(country_code.to_owned()), ..Default::default() }
+    }
+
+    /// The coordinates in the format of the `location` request parameter.
+    #[allow(dead_code)]
+    pub(crate) fn gps_param(&self) -> Option<String> {
+        self.gps.map(|(latitude, longitude)| format!("{},{}", latitude, longitude))
+    }
+}
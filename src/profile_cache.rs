@@ -0,0 +1,144 @@
+//! Remembers the user profile sent for the current device, to leave out what didn't change. Enabled with
+//! [Config::profile_cache](crate::Config::profile_cache).
+
+use std::{collections::HashMap, sync::{Mutex, MutexGuard}};
+use crate::{
+    backend::{Active, Backend},
+    countly::{UserDataOp, Value},
+    user_details::UserDetails,
+};
+
+/// `None` while disabled.
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+#[derive(Debug, Default)]
+struct Profile {
+    /// The last user details sent, without custom properties.
+    details: UserDetails,
+    /// The last value sent for each custom property, by user details or a saved `user_data_set`.
+    custom: HashMap<String, Value>,
+    /// Values of `user_data_set` waiting for `user_data_save`.
+    pending: HashMap<String, Value>,
+}
+
+fn dedup<T: PartialEq + Clone>(new: &mut Option<T>, sent: &mut Option<T>) {
+    if new.is_some() {
+        if new == sent {
+            *new = None;
+        } else {
+            sent.clone_from(new);
+        }
+    }
+}
+
+impl Profile {
+    /// Removes everything that was sent before, returns `None` if nothing is left.
+    fn diff_details(&mut self, mut details: UserDetails) -> Option<UserDetails> {
+        dedup(&mut details.name, &mut self.details.name);
+        dedup(&mut details.username, &mut self.details.username);
+        dedup(&mut details.email, &mut self.details.email);
+        dedup(&mut details.organization, &mut self.details.organization);
+        dedup(&mut details.phone, &mut self.details.phone);
+        dedup(&mut details.picture, &mut self.details.picture);
+        dedup(&mut details.gender, &mut self.details.gender);
+        dedup(&mut details.byear, &mut self.details.byear);
+        details.custom.retain(|key, value| self.custom.get(key) != Some(value));
+        for (key, value) in &details.custom {
+            self.custom.insert(key.clone(), value.clone());
+            self.pending.remove(key);
+        }
+        if details == UserDetails::default() {
+            None
+        } else {
+            Some(details)
+        }
+    }
+
+    /// Whether the modification changes anything. Only the result of `set` is known, everything else makes the
+    /// property unknown.
+    fn changes(&mut self, key: &str, op: &UserDataOp) -> bool {
+        match op {
+            UserDataOp::Set(value) => {
+                let current = self.pending.get(key).or_else(|| self.custom.get(key));
+                if current == Some(value) {
+                    return false;
+                }
+                self.pending.insert(key.to_owned(), value.clone());
+            }
+            _ => {
+                self.custom.remove(key);
+                self.pending.remove(key);
+            }
+        }
+        true
+    }
+
+    fn saved(&mut self) {
+        self.custom.extend(self.pending.drain());
+    }
+}
+
+fn lock() -> MutexGuard<'static, Option<Profile>> {
+    PROFILE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Enables or disables the cache, forgetting what was sent.
+pub(crate) fn configure(enabled: bool) {
+    *lock() = if enabled { Some(Profile::default()) } else { None };
+}
+
+/// Forgets what was sent, if enabled. Called when the device id changes.
+pub(crate) fn clear() {
+    if let Some(profile) = lock().as_mut() {
+        *profile = Profile::default();
+    }
+}
+
+pub(crate) fn set_user_details(details: UserDetails) {
+    let details = match lock().as_mut() {
+        Some(profile) => profile.diff_details(details),
+        None => Some(details),
+    };
+    if let Some(details) = details {
+        Active::set_user_details(details);
+    }
+}
+
+pub(crate) fn user_data(key: &str, op: UserDataOp) {
+    let changes = lock().as_mut().map(|profile| profile.changes(key, &op)).unwrap_or(true);
+    if changes {
+        Active::user_data(key, op);
+    }
+}
+
+pub(crate) fn user_data_save() {
+    if let Some(profile) = lock().as_mut() {
+        profile.saved();
+    }
+    Active::user_data_save();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::user_details::Gender;
+    use super::*;
+
+    #[test]
+    fn leaves_out_unchanged_values() {
+        let mut profile = Profile::default();
+        let details = UserDetails::builder().name("Jane").gender(Gender::Female).custom("plan", "free").build().unwrap();
+        assert_eq!(profile.diff_details(details.clone()), Some(details.clone()));
+        assert_eq!(profile.diff_details(details), None);
+        let changed = UserDetails::builder().name("Jane").byear(1990).custom("plan", "free").build().unwrap();
+        assert_eq!(profile.diff_details(changed), Some(UserDetails::builder().byear(1990).build().unwrap()));
+
+        assert!(!profile.changes("plan", &UserDataOp::Set("free".into())));
+        assert!(profile.changes("plan", &UserDataOp::Set("pro".into())));
+        assert!(!profile.changes("plan", &UserDataOp::Set("pro".into())));
+        profile.saved();
+        assert!(!profile.changes("plan", &UserDataOp::Set("pro".into())));
+        assert!(profile.changes("logins", &UserDataOp::Set(1.into())));
+        assert!(profile.changes("logins", &UserDataOp::Increment));
+        assert!(profile.changes("logins", &UserDataOp::Set(1.into())));
+    }
+}
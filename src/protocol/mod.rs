@@ -0,0 +1,43 @@
+//! Platform independent parts of the Rust implementation of the Countly protocol, used by every backend that
+//! does not forward to a JavaScript SDK.
+
+#[cfg(any(feature = "pure", feature = "native"))]
+pub(crate) mod queue;
+pub(crate) mod request;
+#[cfg(any(feature = "pure", feature = "native"))]
+pub(crate) mod scheduler;
+#[cfg(any(feature = "pure", feature = "native"))]
+pub(crate) mod tracker;
+
+/// A point in time as Countly wants to see it: milliseconds since the epoch plus the local hour and day of week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Now {
+    pub timestamp: u64,
+    /// 0-23
+    pub hour: u32,
+    /// 0 = Sunday
+    pub dow: u32,
+    /// Offset of the local time zone to UTC in minutes.
+    pub tz: i32,
+}
+
+impl Now {
+    /// `timestamp` with hour and day of week in UTC, for when the local time zone isn't known.
+    #[allow(dead_code)] // Not used in the browser.
+    pub fn utc(timestamp: u64) -> Self {
+        let secs = timestamp / 1000;
+        Self {
+            timestamp,
+            hour: ((secs / 3600) % 24) as u32,
+            // 1970-01-01 was a Thursday.
+            dow: ((secs / 86400 + 4) % 7) as u32,
+            tz: 0,
+        }
+    }
+
+    /// Milliseconds elapsed since `earlier`.
+    #[cfg(any(feature = "pure", feature = "native"))]
+    pub fn since(&self, earlier: u64) -> u64 {
+        self.timestamp.saturating_sub(earlier)
+    }
+}
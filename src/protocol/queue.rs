@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use super::request::Request;
+
+/// Default for [Config::queue_size](crate::Config::queue_size).
+pub(crate) const DEFAULT_QUEUE_SIZE: usize = 1000;
+
+/// Keeps a copy of the request queue somewhere that survives a reload.
+///
+/// The queue itself is always kept in memory, implementations are only notified about changes. By default every
+/// change rewrites the whole queue via [Persist::store].
+pub(crate) trait Persist {
+    /// Returns the requests stored by a previous run.
+    fn load(&mut self) -> Vec<Request>;
+
+    /// Replaces everything stored with `queue`.
+    fn store(&mut self, queue: &VecDeque<Request>);
+
+    /// `request` was appended to the end of `queue`.
+    fn appended(&mut self, queue: &VecDeque<Request>, _request: &Request) {
+        self.store(queue);
+    }
+
+    /// `count` requests were removed from the front of `queue`.
+    fn removed_front(&mut self, queue: &VecDeque<Request>, _count: usize) {
+        self.store(queue);
+    }
+}
+
+/// Not keeping anything, for when there is no storage configured.
+impl<P: Persist> Persist for Option<P> {
+    fn load(&mut self) -> Vec<Request> {
+        self.as_mut().map(Persist::load).unwrap_or_default()
+    }
+
+    fn store(&mut self, queue: &VecDeque<Request>) {
+        if let Some(persist) = self {
+            persist.store(queue);
+        }
+    }
+
+    fn appended(&mut self, queue: &VecDeque<Request>, request: &Request) {
+        if let Some(persist) = self {
+            persist.appended(queue, request);
+        }
+    }
+
+    fn removed_front(&mut self, queue: &VecDeque<Request>, count: usize) {
+        if let Some(persist) = self {
+            persist.removed_front(queue, count);
+        }
+    }
+}
+
+/// Keeping nothing, for tests.
+#[cfg(test)]
+impl Persist for () {
+    fn load(&mut self) -> Vec<Request> {
+        Vec::new()
+    }
+
+    fn store(&mut self, _queue: &VecDeque<Request>) {}
+}
+
+/// Requests waiting to be sent to the server, oldest first.
+pub(crate) struct Queue<P> {
+    requests: VecDeque<Request>,
+    limit: usize,
+    persist: P,
+}
+
+impl<P: Persist> Queue<P> {
+    /// Creates the queue, restoring everything `persist` kept from the last run.
+    pub fn new(limit: Option<u32>, mut persist: P) -> Self {
+        let limit = limit.map(|limit| limit.max(1) as usize).unwrap_or(DEFAULT_QUEUE_SIZE);
+        let mut requests: VecDeque<Request> = persist.load().into();
+        if requests.len() > limit {
+            requests.drain(..requests.len() - limit);
+            persist.store(&requests);
+        }
+        Self { requests, limit, persist }
+    }
+
+    /// Appends a request. If the queue is full, the oldest request is dropped.
+    pub fn push(&mut self, request: Request) {
+        let overflow = (self.requests.len() + 1).saturating_sub(self.limit);
+        if overflow > 0 {
+            self.requests.drain(..overflow);
+            self.persist.removed_front(&self.requests, overflow);
+        }
+        self.requests.push_back(request);
+        let request = self.requests.back().unwrap();
+        self.persist.appended(&self.requests, request);
+    }
+
+    pub fn front(&self) -> Option<&Request> {
+        self.requests.front()
+    }
+
+    /// All queued requests, oldest first.
+    #[allow(dead_code)] // Only inspected by the native backend.
+    pub fn iter(&self) -> impl Iterator<Item = &Request> {
+        self.requests.iter()
+    }
+
+    pub fn pop_front(&mut self) -> Option<Request> {
+        let request = self.requests.pop_front()?;
+        self.persist.removed_front(&self.requests, 1);
+        Some(request)
+    }
+
+    /// Modifies all queued requests, for example to replace the device id.
+    pub fn update_all(&mut self, f: impl FnMut(&mut Request)) {
+        self.requests.iter_mut().for_each(f);
+        self.persist.store(&self.requests);
+    }
+}
@@ -0,0 +1,154 @@
+// Imports only need the bulk part of this.
+#![cfg_attr(not(any(feature = "pure", feature = "native")), allow(dead_code))]
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::Config;
+use super::Now;
+
+/// Name reported to the server as `sdk_name`.
+pub(crate) const SDK_NAME: &str = "countly-rs";
+/// Version reported to the server as `sdk_version`.
+pub(crate) const SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Above this length, requests are sent as POST even if `force_post` is not set.
+const MAX_GET_LENGTH: usize = 2000;
+
+/// A single request to the `/i` endpoint, as a list of query parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Request {
+    params: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Creates a request with the parameters every request needs.
+    pub fn new(app_key: &str, device_id: &str, now: Now) -> Self {
+        let mut request = Self { params: Vec::new() };
+        request
+            .set("app_key", app_key)
+            .set("device_id", device_id)
+            .set("sdk_name", SDK_NAME)
+            .set("sdk_version", SDK_VERSION)
+            .set("timestamp", now.timestamp.to_string())
+            .set("hour", now.hour.to_string())
+            .set("dow", now.dow.to_string())
+            .set("tz", now.tz.to_string());
+        request
+    }
+
+    /// Sets a parameter, replacing a previous value with the same name.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) -> &mut Self {
+        let value = value.into();
+        if let Some(param) = self.params.iter_mut().find(|(k, _)| k == key) {
+            param.1 = value;
+        } else {
+            self.params.push((key.to_owned(), value));
+        }
+        self
+    }
+
+    /// Sets a parameter to the JSON representation of `value`.
+    pub fn set_json(&mut self, key: &str, value: &impl Serialize) -> &mut Self {
+        self.set(key, serde_json::to_string(value).expect("Failed serializing request parameter"))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Combines several requests into one for the `/i/bulk` endpoint.
+    #[cfg_attr(not(feature = "import"), allow(dead_code))]
+    pub fn bulk(app_key: &str, requests: &[Request]) -> Self {
+        let requests: Vec<serde_json::Map<String, serde_json::Value>> = requests.iter()
+            .map(|request| request.params.iter().map(|(k, v)| (k.clone(), serde_json::Value::from(v.as_str()))).collect())
+            .collect();
+        let mut request = Self { params: Vec::new() };
+        request.set("app_key", app_key).set_json("requests", &requests);
+        request
+    }
+
+    /// The URL encoded parameters, usable as a query string or a form body.
+    pub fn to_query(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.params.iter())
+            .finish()
+    }
+}
+
+/// Where and how requests are sent, taken from the [Config].
+#[derive(Debug, Clone)]
+pub(crate) struct Server {
+    pub url: String,
+    pub force_post: bool,
+    pub salt: Option<String>,
+}
+
+impl Server {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            url: config.url.trim_end_matches('/').to_owned(),
+            force_post: config.force_post,
+            salt: config.salt.clone(),
+        }
+    }
+
+    /// URL of the `/i` endpoint.
+    pub fn endpoint(&self) -> String {
+        format!("{}/i", self.url)
+    }
+
+    /// URL of the `/i/bulk` endpoint.
+    #[cfg_attr(not(feature = "import"), allow(dead_code))]
+    pub fn bulk_endpoint(&self) -> String {
+        format!("{}/i/bulk", self.url)
+    }
+
+    /// The URL encoded parameters of `request`, followed by `checksum256` if a salt is configured.
+    ///
+    /// The server verifies the checksum by hashing exactly what it received up to the checksum parameter, so the
+    /// result must be sent as is.
+    pub fn encode(&self, request: &Request) -> String {
+        let query = request.to_query();
+        match &self.salt {
+            Some(salt) => {
+                let checksum = checksum(&query, salt);
+                format!("{}&checksum256={}", query, checksum)
+            }
+            None => query,
+        }
+    }
+
+    /// Whether the encoded parameters should be sent in the body of a POST request instead of the query string.
+    pub fn use_post(&self, encoded: &str) -> bool {
+        self.force_post || encoded.len() > MAX_GET_LENGTH
+    }
+}
+
+/// SHA-256 of the parameters and the salt, as hex.
+pub(crate) fn checksum(query: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(query.as_bytes());
+    hasher.update(salt.as_bytes());
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_requests_with_salt() {
+        let request = Request { params: vec![("app_key".to_owned(), "key".to_owned()), ("events".to_owned(), "[]".to_owned())] };
+        let mut config = Config::new("key", "http://localhost/");
+        assert_eq!(Server::new(&config).encode(&request), "app_key=key&events=%5B%5D");
+
+        config.salt = Some("secret".to_owned());
+        let server = Server::new(&config);
+        // The hash covers the encoded parameters exactly as sent, followed by the salt.
+        assert_eq!(
+            server.encode(&request),
+            "app_key=key&events=%5B%5D&checksum256=9997468155f172e2d147cfa31ed89b21eb503eeb6a4a6b74bad9d437232105c7",
+        );
+        assert_eq!(server.endpoint(), "http://localhost/i");
+    }
+}
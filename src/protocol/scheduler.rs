@@ -0,0 +1,155 @@
+use crate::Config;
+use super::{Now, queue::Persist, request::Request, tracker::Tracker};
+
+/// Default for [Config::interval](crate::Config::interval), in milliseconds.
+const DEFAULT_INTERVAL: f64 = 500.0;
+/// Default for [Config::fail_timeout](crate::Config::fail_timeout), in seconds.
+const DEFAULT_FAIL_TIMEOUT: f64 = 60.0;
+/// The pause after consecutive failures doubles each time, up to this multiple of `fail_timeout`.
+const MAX_BACKOFF_FACTOR: u64 = 64;
+
+/// Source of the current time, so the scheduler can be driven by a fake clock in tests.
+pub(crate) trait Clock {
+    fn now(&self) -> Now;
+}
+
+/// Decides when to flush events and when to send the next request, the way the Web SDK does.
+///
+/// Every `interval`, recorded events are moved into requests (in batches of `max_events`) and the session heartbeat
+/// is checked. Requests are sent one at a time, in order. After a failed request sending is paused for
+/// `fail_timeout`, doubling with every consecutive failure, plus up to 25% of random jitter so that many clients
+/// don't retry in lockstep.
+pub(crate) struct Scheduler {
+    interval: u64,
+    fail_timeout: u64,
+    failures: u32,
+    next_tick: u64,
+    paused_until: u64,
+    sending: bool,
+    rng: u64,
+}
+
+impl Scheduler {
+    /// `seed` initializes the jitter, any value works.
+    pub fn new(config: &Config, seed: u64) -> Self {
+        Self {
+            interval: config.interval.unwrap_or(DEFAULT_INTERVAL).max(1.0) as u64,
+            fail_timeout: (config.fail_timeout.unwrap_or(DEFAULT_FAIL_TIMEOUT) * 1000.0) as u64,
+            failures: 0,
+            next_tick: 0,
+            paused_until: 0,
+            sending: false,
+            // xorshift gets stuck on zero.
+            rng: seed | 1,
+        }
+    }
+
+    /// Runs the periodic housekeeping if it's due and returns the request to send next, if sending is possible right now.
+    ///
+    /// Once a request has been returned, no further one is until [Scheduler::complete] is called.
+    pub fn poll<P: Persist>(&mut self, tracker: &mut Tracker<P>, now: Now) -> Option<Request> {
+        if now.timestamp >= self.next_tick {
+            tracker.tick(now);
+            self.next_tick = now.timestamp + self.interval;
+        }
+        if self.sending || tracker.is_offline() || now.timestamp < self.paused_until {
+            return None;
+        }
+        let request = tracker.queue.front()?.clone();
+        self.sending = true;
+        Some(request)
+    }
+
+    /// Reports the result of sending a request returned by [Scheduler::poll].
+    pub fn complete<P: Persist>(&mut self, tracker: &mut Tracker<P>, request: &Request, success: bool, now: Now) {
+        self.sending = false;
+        if success {
+            self.failures = 0;
+            // The queue might have been changed in the meantime, only remove what was actually sent.
+            if tracker.queue.front() == Some(request) {
+                tracker.queue.pop_front();
+            }
+        } else {
+            self.failures += 1;
+            self.paused_until = now.timestamp + self.backoff();
+        }
+    }
+
+    /// Milliseconds until [Scheduler::poll] should be called again.
+    #[allow(dead_code)] // Backends either sleep for this long or poll at a fixed interval.
+    pub fn wait(&self, now: Now) -> u64 {
+        self.next_tick.saturating_sub(now.timestamp)
+    }
+
+    /// How often [Scheduler::poll] should be called, in milliseconds.
+    #[allow(dead_code)]
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Whether a request returned by [Scheduler::poll] hasn't been completed yet.
+    #[allow(dead_code)]
+    pub fn is_sending(&self) -> bool {
+        self.sending
+    }
+
+    fn backoff(&mut self) -> u64 {
+        let factor = 1u64.checked_shl(self.failures.saturating_sub(1)).unwrap_or(u64::MAX).min(MAX_BACKOFF_FACTOR);
+        let delay = self.fail_timeout.saturating_mul(factor);
+        delay + self.random() % (delay / 4 + 1)
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Map, Value as Json};
+    use crate::{countly::CustomEvent, device_id::DeviceIdType, protocol::queue::Queue};
+    use super::*;
+
+    fn at(timestamp: u64) -> Now {
+        Now { timestamp, hour: 0, dow: 0, tz: 0 }
+    }
+
+    /// Sends the next request with the given outcome, returns how many events it had.
+    fn send(tracker: &mut Tracker<()>, scheduler: &mut Scheduler, now: u64, success: bool) -> Option<usize> {
+        let request = scheduler.poll(tracker, at(now))?;
+        scheduler.complete(tracker, &request, success, at(now));
+        Some(serde_json::from_str::<Vec<Json>>(request.get("events").unwrap()).unwrap().len())
+    }
+
+    #[test]
+    fn batches_and_backs_off() {
+        let mut config = Config::new("key", "http://localhost");
+        config.interval = Some(1000.0);
+        config.fail_timeout = Some(10.0);
+        config.max_events = Some(10);
+        let mut scheduler = Scheduler::new(&config, 42);
+        let mut tracker = Tracker::new(config, Queue::new(None, ()), "device".to_owned(), DeviceIdType::SdkGenerated, "test", Map::new());
+
+        assert_eq!(send(&mut tracker, &mut scheduler, 0, true), None);
+        for _ in 0..25 {
+            tracker.add_event(CustomEvent { key: "click".to_owned(), count: 1, ..Default::default() }, at(100));
+        }
+        // Two full batches go out right away, the rest waits for the next interval.
+        assert_eq!(send(&mut tracker, &mut scheduler, 100, false), Some(10));
+
+        // Paused for at least fail_timeout, with up to 25% jitter.
+        assert_eq!(send(&mut tracker, &mut scheduler, 10_099, false), None);
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601, false), Some(10));
+
+        // The second failure doubles the pause.
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601 + 19_999, true), None);
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601 + 25_001, true), Some(10));
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601 + 25_001, true), Some(10));
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601 + 25_001, true), Some(5));
+        assert_eq!(send(&mut tracker, &mut scheduler, 12_601 + 25_001, true), None);
+        assert_eq!(tracker.queue.iter().count(), 0);
+    }
+}
@@ -0,0 +1,715 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use serde_json::{Map, Value as Json, json};
+use crate::{
+    Config,
+    countly::{CustomEvent, UserDataOp, Value},
+    device_id::{DeviceIdChange, DeviceIdType},
+    gdpr::ConsentFeatures,
+    location::Location,
+    user_details::UserDetails,
+};
+use super::{Now, queue::{Persist, Queue}, request::Request};
+
+/// Default for [Config::max_events](crate::Config::max_events).
+const DEFAULT_MAX_EVENTS: usize = 10;
+/// Default for [Config::max_logs](crate::Config::max_logs).
+const DEFAULT_MAX_LOGS: usize = 100;
+/// Default for [Config::session_update](crate::Config::session_update), in seconds.
+const DEFAULT_SESSION_UPDATE: f64 = 60.0;
+/// Default for [Config::inactivity_time](crate::Config::inactivity_time), in minutes.
+const DEFAULT_INACTIVITY_TIME: f64 = 20.0;
+
+/// Event key Countly uses for views.
+pub(crate) const VIEW_EVENT: &str = "[CLY]_view";
+/// Device id used in offline mode until a real one is provided.
+pub(crate) const TEMP_DEVICE_ID: &str = "[CLY]_temp_id";
+
+struct Session {
+    /// When the session was begun or last extended.
+    last_beat: u64,
+    /// Whether [Tracker::tick] should extend the session.
+    heartbeat: bool,
+}
+
+struct View {
+    name: String,
+    start: u64,
+}
+
+struct Pause {
+    since: u64,
+    /// The session with heartbeat was ended for inactivity, a new one is begun on resume.
+    session_ended: bool,
+}
+
+/// Turns calls to the public API into requests on the queue, the way the Web SDK does.
+///
+/// The tracker does not know anything about time, storage or networking, everything platform specific is passed in.
+pub(crate) struct Tracker<P> {
+    pub config: Config,
+    pub queue: Queue<P>,
+    device_id: String,
+    device_id_type: DeviceIdType,
+    /// The id to return to when offline mode ends without a new one.
+    suspended_device_id: Option<(String, DeviceIdType)>,
+    offline: bool,
+    ignored: bool,
+    /// Metrics sent with `begin_session` and crash reports, like `_os` or `_resolution`.
+    metrics: Map<String, Json>,
+    /// Segment reported with views, like "Web".
+    platform: String,
+    events: Vec<CustomEvent>,
+    timed_events: HashMap<String, u64>,
+    user_data: Map<String, Json>,
+    logs: VecDeque<String>,
+    consents: HashSet<String>,
+    groups: HashMap<String, Vec<ConsentFeatures>>,
+    session: Option<Session>,
+    view: Option<View>,
+    crash_segments: Option<HashMap<String, String>>,
+    /// Set by [Tracker::set_location], takes precedence over the configuration.
+    location: Option<Location>,
+    location_disabled: bool,
+    /// Set while the page is hidden.
+    paused: Option<Pause>,
+}
+
+impl<P: Persist> Tracker<P> {
+    /// `device_id` is the stored or generated id, it is only used if the configuration doesn't provide one.
+    pub fn new(
+        config: Config,
+        queue: Queue<P>,
+        device_id: String,
+        device_id_type: DeviceIdType,
+        platform: &str,
+        metrics: Map<String, Json>,
+    ) -> Self {
+        let offline = config.offline_mode;
+        let (device_id, device_id_type, suspended_device_id) = match &config.device_id {
+            Some(id) => (id.clone(), DeviceIdType::DeveloperSupplied, None),
+            None if offline => (TEMP_DEVICE_ID.to_owned(), DeviceIdType::Temporary, Some((device_id, device_id_type))),
+            None => (device_id, device_id_type, None),
+        };
+        Self {
+            ignored: config.ignore_visitor,
+            config,
+            queue,
+            device_id,
+            device_id_type,
+            suspended_device_id,
+            offline,
+            metrics,
+            platform: platform.to_owned(),
+            events: Vec::new(),
+            timed_events: HashMap::new(),
+            user_data: Map::new(),
+            logs: VecDeque::new(),
+            consents: HashSet::new(),
+            groups: HashMap::new(),
+            session: None,
+            view: None,
+            crash_segments: None,
+            location: None,
+            location_disabled: false,
+            paused: None,
+        }
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub fn device_id_type(&self) -> DeviceIdType {
+        self.device_id_type
+    }
+
+    /// Whether requests must stay in the queue for now.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn set_ignored(&mut self, ignored: bool) {
+        self.ignored = ignored;
+    }
+
+    pub fn has_consent(&self, feature: ConsentFeatures) -> bool {
+        let feature: &'static str = feature.into();
+        !self.config.require_consent || self.consents.contains(feature)
+    }
+
+    fn request(&self, now: Now) -> Request {
+        Request::new(&self.config.app_key, &self.device_id, now)
+    }
+
+    fn enqueue(&mut self, request: Request) {
+        if !self.ignored {
+            self.queue.push(request);
+        }
+    }
+
+    fn max_events(&self) -> usize {
+        self.config.max_events.map(|max| max.max(1) as usize).unwrap_or(DEFAULT_MAX_EVENTS)
+    }
+
+    /// Records an event, it is sent with the next batch.
+    pub fn add_event(&mut self, mut event: CustomEvent, now: Now) {
+        let feature = if event.key == VIEW_EVENT { ConsentFeatures::Views } else { ConsentFeatures::Events };
+        if !self.has_consent(feature) {
+            return;
+        }
+        event.timestamp.get_or_insert(now.timestamp);
+        event.hour.get_or_insert(now.hour);
+        event.dow.get_or_insert(now.dow);
+        self.events.push(event);
+        if self.events.len() >= self.max_events() {
+            self.flush_events(now);
+        }
+    }
+
+    /// Moves all recorded events into requests on the queue.
+    pub fn flush_events(&mut self, now: Now) {
+        let max_events = self.max_events();
+        while !self.events.is_empty() {
+            let batch: Vec<_> = self.events.drain(..self.events.len().min(max_events)).collect();
+            let mut request = self.request(now);
+            request.set_json("events", &batch);
+            self.enqueue(request);
+        }
+    }
+
+    pub fn start_event(&mut self, key: &str, now: Now) {
+        self.timed_events.entry(key.to_owned()).or_insert(now.timestamp);
+    }
+
+    pub fn end_event(&mut self, key: &str, now: Now) {
+        if let Some(start) = self.timed_events.remove(key) {
+            self.add_event(CustomEvent {
+                key: key.to_owned(),
+                count: 1,
+                duration: Some(self.active_since(start, now) as f64 / 1000.0),
+                ..Default::default()
+            }, now);
+        }
+    }
+
+    /// Ends the current view (reporting its duration) and starts a new one.
+    pub fn track_view(&mut self, name: &str, domain: Option<&str>, now: Now) {
+        if !self.has_consent(ConsentFeatures::Views) {
+            return;
+        }
+        let first = self.view.is_none();
+        self.end_view(now);
+        let mut segmentation = HashMap::new();
+        segmentation.insert("name".to_owned(), name.into());
+        segmentation.insert("visit".to_owned(), 1.into());
+        segmentation.insert("segment".to_owned(), self.platform.as_str().into());
+        if first {
+            segmentation.insert("start".to_owned(), 1.into());
+        }
+        if let Some(domain) = domain {
+            segmentation.insert("domain".to_owned(), domain.into());
+        }
+        self.add_event(CustomEvent { key: VIEW_EVENT.to_owned(), count: 1, segmentation, ..Default::default() }, now);
+        self.view = Some(View { name: name.to_owned(), start: now.timestamp });
+    }
+
+    /// Milliseconds since `start`, without the time the page has been hidden for now.
+    fn active_since(&self, start: u64, now: Now) -> u64 {
+        let end = self.paused.as_ref().map(|pause| pause.since.min(now.timestamp)).unwrap_or(now.timestamp);
+        end.saturating_sub(start)
+    }
+
+    /// Reports the duration of the current view, if there is one.
+    pub fn end_view(&mut self, now: Now) {
+        if let Some(view) = self.view.take() {
+            let mut segmentation = HashMap::new();
+            segmentation.insert("name".to_owned(), view.name.into());
+            segmentation.insert("segment".to_owned(), self.platform.as_str().into());
+            self.add_event(CustomEvent {
+                key: VIEW_EVENT.to_owned(),
+                count: 1,
+                duration: Some(self.active_since(view.start, now) as f64 / 1000.0),
+                segmentation,
+                ..Default::default()
+            }, now);
+        }
+    }
+
+    pub fn begin_session(&mut self, heartbeat: bool, now: Now) {
+        if self.session.is_some() || !self.has_consent(ConsentFeatures::Sessions) {
+            return;
+        }
+        let mut request = self.request(now);
+        request.set("begin_session", "1").set_json("metrics", &self.metrics);
+        self.add_location(&mut request);
+        self.enqueue(request);
+        self.session = Some(Session { last_beat: now.timestamp, heartbeat });
+    }
+
+    /// Reports the session duration since the last call, without the time the page has been hidden for now. `secs`
+    /// overrides the measured time.
+    pub fn extend_session(&mut self, secs: Option<f64>, now: Now) {
+        let measured = self.session.as_ref().map(|session| self.active_since(session.last_beat, now));
+        if let (Some(session), Some(measured)) = (&mut self.session, measured) {
+            let secs = secs.unwrap_or(measured as f64 / 1000.0);
+            session.last_beat = now.timestamp;
+            let mut request = self.request(now);
+            request.set("session_duration", (secs.round() as u64).to_string());
+            self.enqueue(request);
+        }
+    }
+
+    pub fn end_session(&mut self, secs: Option<f64>, now: Now) {
+        self.end_view(now);
+        self.flush_events(now);
+        if let Some(session) = self.session.take() {
+            let secs = secs.unwrap_or_else(|| self.active_since(session.last_beat, now) as f64 / 1000.0);
+            let mut request = self.request(now);
+            request.set("end_session", "1").set("session_duration", (secs.round() as u64).to_string());
+            self.enqueue(request);
+        }
+    }
+
+    /// Periodic housekeeping: sends recorded events and extends the session when it is due.
+    pub fn tick(&mut self, now: Now) {
+        self.flush_events(now);
+        if self.paused.is_some() {
+            self.end_inactive_session(now);
+            return;
+        }
+        let session_update = self.config.session_update.unwrap_or(DEFAULT_SESSION_UPDATE) * 1000.0;
+        let due = match &self.session {
+            Some(session) => session.heartbeat && now.since(session.last_beat) as f64 >= session_update,
+            None => false,
+        };
+        if due {
+            self.extend_session(None, now);
+        }
+    }
+
+    /// Ends the session with heartbeat once the page has been hidden for `inactivity_time`, as of when it was hidden.
+    fn end_inactive_session(&mut self, now: Now) {
+        let inactivity = self.config.inactivity_time.unwrap_or(DEFAULT_INACTIVITY_TIME) * 60_000.0;
+        let heartbeat = self.session.as_ref().map(|session| session.heartbeat).unwrap_or(false);
+        let since = match &self.paused {
+            Some(pause) if heartbeat && now.since(pause.since) as f64 >= inactivity => pause.since,
+            _ => return,
+        };
+        self.end_session(None, Now { timestamp: since, ..now });
+        if let Some(pause) = &mut self.paused {
+            pause.session_ended = true;
+        }
+    }
+
+    fn add_location(&self, request: &mut Request) {
+        if self.location_disabled || !self.has_consent(ConsentFeatures::Location) {
+            return;
+        }
+        let (country_code, city, ip_address) = match &self.location {
+            Some(location) => {
+                if let Some(gps) = location.gps_param() {
+                    request.set("location", gps);
+                }
+                (&location.country_code, &location.city, &location.ip_address)
+            }
+            None => (&self.config.country_code, &self.config.city, &self.config.ip_address),
+        };
+        if let Some(country_code) = country_code {
+            request.set("country_code", country_code.as_str());
+        }
+        if let Some(city) = city {
+            request.set("city", city.as_str());
+        }
+        if let Some(ip_address) = ip_address {
+            request.set("ip_address", ip_address.as_str());
+        }
+    }
+
+    /// Replaces the configured location and reports it right away.
+    pub fn set_location(&mut self, location: Location, now: Now) {
+        self.location = Some(location);
+        self.location_disabled = false;
+        if self.has_consent(ConsentFeatures::Location) {
+            let mut request = self.request(now);
+            self.add_location(&mut request);
+            self.enqueue(request);
+        }
+    }
+
+    /// Stops sending location, and makes the server forget the one it has.
+    pub fn disable_location(&mut self, now: Now) {
+        self.location = None;
+        self.location_disabled = true;
+        let mut request = self.request(now);
+        request.set("location", "");
+        self.enqueue(request);
+    }
+
+    pub fn user_details(&mut self, details: &UserDetails, now: Now) {
+        if !self.has_consent(ConsentFeatures::Users) {
+            return;
+        }
+        let mut request = self.request(now);
+        request.set_json("user_details", details);
+        self.enqueue(request);
+    }
+
+    /// Collects a custom property modification, sent with [Tracker::user_data_save].
+    pub fn user_data(&mut self, key: &str, op: UserDataOp) {
+        let (modifier, value) = match op {
+            UserDataOp::Set(value) => {
+                self.user_data.insert(key.to_owned(), value_to_json(&value));
+                return;
+            }
+            UserDataOp::Unset => {
+                self.user_data.insert(key.to_owned(), Json::from(""));
+                return;
+            }
+            UserDataOp::SetOnce(value) => ("$setOnce", value_to_json(&value)),
+            UserDataOp::Increment => ("$inc", Json::from(1)),
+            UserDataOp::IncrementBy(value) => ("$inc", Json::from(value)),
+            UserDataOp::Multiply(value) => ("$mul", Json::from(value)),
+            UserDataOp::Max(value) => ("$max", Json::from(value)),
+            UserDataOp::Min(value) => ("$min", Json::from(value)),
+            UserDataOp::Push(value) => return self.user_data_array(key, "$push", value),
+            UserDataOp::PushUnique(value) => return self.user_data_array(key, "$addToSet", value),
+            UserDataOp::Pull(value) => return self.user_data_array(key, "$pull", value),
+        };
+        self.user_data.insert(key.to_owned(), json!({ modifier: value }));
+    }
+
+    fn user_data_array(&mut self, key: &str, modifier: &str, value: Value) {
+        let entry = self.user_data.entry(key.to_owned()).or_insert_with(|| json!({}));
+        if !entry.is_object() {
+            *entry = json!({});
+        }
+        let values = entry.as_object_mut().unwrap().entry(modifier.to_owned()).or_insert_with(|| json!([]));
+        if let Json::Array(values) = values {
+            match value_to_json(&value) {
+                Json::Array(items) => values.extend(items),
+                item => values.push(item),
+            }
+        }
+    }
+
+    pub fn user_data_save(&mut self, now: Now) {
+        if self.user_data.is_empty() || !self.has_consent(ConsentFeatures::Users) {
+            return;
+        }
+        let custom = std::mem::take(&mut self.user_data);
+        let mut request = self.request(now);
+        request.set_json("user_details", &json!({ "custom": custom }));
+        self.enqueue(request);
+    }
+
+    /// Adds a breadcrumb to be sent with the next crash report.
+    pub fn add_log(&mut self, msg: &str) {
+        let max_logs = self.config.max_logs.map(|max| max as usize).unwrap_or(DEFAULT_MAX_LOGS);
+        self.logs.push_back(msg.to_owned());
+        while self.logs.len() > max_logs {
+            self.logs.pop_front();
+        }
+    }
+
+    /// Sets the default segments for crash reports.
+    pub fn set_crash_segments(&mut self, segments: Option<HashMap<String, String>>) {
+        self.crash_segments = segments;
+    }
+
+    pub fn log_error(&mut self, error: &str, nonfatal: bool, segments: Option<HashMap<String, String>>, now: Now) {
+        if !self.has_consent(ConsentFeatures::Crashes) {
+            return;
+        }
+        let mut crash = self.metrics.clone();
+        crash.insert("_error".to_owned(), Json::from(error));
+        crash.insert("_nonfatal".to_owned(), Json::from(nonfatal));
+        crash.insert("_logs".to_owned(), Json::from(self.logs.iter().cloned().collect::<Vec<_>>().join("\n")));
+        if let Some(segments) = segments.as_ref().or(self.crash_segments.as_ref()) {
+            crash.insert("_custom".to_owned(), serde_json::to_value(segments).unwrap());
+        }
+        self.logs.clear();
+        let mut request = self.request(now);
+        request.set_json("crash", &crash);
+        self.enqueue(request);
+    }
+
+    pub fn report_conversion(&mut self, campaign_id: &str, now: Now) {
+        if !self.has_consent(ConsentFeatures::Attribution) {
+            return;
+        }
+        let mut request = self.request(now);
+        request.set("campaign_id", campaign_id);
+        self.enqueue(request);
+    }
+
+    pub fn change_device_id(&mut self, id: &str, change: DeviceIdChange, now: Now) {
+        if self.device_id_type == DeviceIdType::Temporary {
+            // Nothing has been sent with the temporary id yet, so just claim everything for the new one.
+            self.disable_offline_mode(Some(id));
+            return;
+        }
+        if id == self.device_id {
+            return;
+        }
+        match change {
+            DeviceIdChange::WithMerge => {
+                self.flush_events(now);
+                let mut request = self.request(now);
+                request.set("device_id", id).set("old_device_id", self.device_id.as_str());
+                self.device_id = id.to_owned();
+                self.enqueue(request);
+            }
+            DeviceIdChange::WithoutMerge => {
+                let heartbeat = self.session.as_ref().map(|session| session.heartbeat);
+                self.end_session(None, now);
+                self.device_id = id.to_owned();
+                if let Some(heartbeat) = heartbeat {
+                    self.begin_session(heartbeat, now);
+                }
+            }
+        }
+        self.device_id_type = DeviceIdType::DeveloperSupplied;
+    }
+
+    fn replace_queued_device_id(&mut self, id: &str) {
+        let old = std::mem::replace(&mut self.device_id, id.to_owned());
+        self.queue.update_all(|request| {
+            if request.get("device_id") == Some(old.as_str()) {
+                request.set("device_id", id);
+            }
+        });
+    }
+
+    /// Switches to the temporary device id, like the JavaScript SDKs. What was recorded before keeps the old id.
+    pub fn enable_offline_mode(&mut self, now: Now) {
+        self.offline = true;
+        if self.device_id_type != DeviceIdType::Temporary {
+            self.flush_events(now);
+            let id = std::mem::replace(&mut self.device_id, TEMP_DEVICE_ID.to_owned());
+            self.suspended_device_id = Some((id, self.device_id_type));
+            self.device_id_type = DeviceIdType::Temporary;
+        }
+    }
+
+    /// Without a new id, the one used before offline mode is restored.
+    pub fn disable_offline_mode(&mut self, device_id: Option<&str>) {
+        self.offline = false;
+        let (id, device_id_type) = match (device_id, self.suspended_device_id.take()) {
+            (Some(id), _) => (id.to_owned(), DeviceIdType::DeveloperSupplied),
+            (None, Some(suspended)) => suspended,
+            (None, None) => return,
+        };
+        if self.device_id == TEMP_DEVICE_ID {
+            self.replace_queued_device_id(&id);
+        } else {
+            self.device_id = id;
+        }
+        self.device_id_type = device_id_type;
+    }
+
+    pub fn group_features(&mut self, groups: HashMap<String, Vec<ConsentFeatures>>) {
+        self.groups.extend(groups);
+    }
+
+    /// Resolves feature group names to the features they contain.
+    fn expand_features(&self, features: &[&str]) -> Vec<String> {
+        let mut expanded = Vec::new();
+        for feature in features {
+            if let Some(group) = self.groups.get(*feature) {
+                expanded.extend(group.iter().map(|feature| Into::<&'static str>::into(*feature).to_owned()));
+            } else {
+                expanded.push((*feature).to_owned());
+            }
+        }
+        expanded
+    }
+
+    pub fn add_consent(&mut self, features: &[&str], now: Now) {
+        let features = self.expand_features(features);
+        self.consents.extend(features.iter().cloned());
+        self.send_consent(&features, true, now);
+    }
+
+    pub fn remove_consent(&mut self, features: &[&str], now: Now) {
+        let features = self.expand_features(features);
+        if features.iter().any(|feature| feature == Into::<&'static str>::into(ConsentFeatures::Sessions)) {
+            self.end_session(None, now);
+        }
+        self.send_consent(&features, false, now);
+        for feature in &features {
+            self.consents.remove(feature);
+        }
+    }
+
+    fn send_consent(&mut self, features: &[String], granted: bool, now: Now) {
+        let consent: Map<String, Json> = features.iter().map(|feature| (feature.clone(), Json::from(granted))).collect();
+        let mut request = self.request(now);
+        request.set_json("consent", &consent);
+        self.enqueue(request);
+    }
+}
+
+/// Pausing while the page is hidden: the session isn't extended and the time doesn't count for timed events and views.
+#[cfg(all(feature = "pure", any(target_arch = "wasm32", not(feature = "native"))))]
+impl<P: Persist> Tracker<P> {
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// Reports the session with heartbeat up to now, then stops its heartbeats.
+    pub fn pause(&mut self, now: Now) {
+        if self.paused.is_some() {
+            return;
+        }
+        if self.session.as_ref().map(|session| session.heartbeat).unwrap_or(false) {
+            self.extend_session(None, now);
+        }
+        self.paused = Some(Pause { since: now.timestamp, session_ended: false });
+    }
+
+    /// Continues the session with heartbeat, or begins a new one if it ended for inactivity.
+    pub fn resume(&mut self, now: Now) {
+        let pause = match self.paused.take() {
+            Some(pause) => pause,
+            None => return,
+        };
+        let hidden = now.since(pause.since);
+        for start in self.timed_events.values_mut() {
+            *start += hidden;
+        }
+        if let Some(view) = &mut self.view {
+            view.start += hidden;
+        }
+        if pause.session_ended {
+            self.begin_session(true, now);
+        } else if let Some(session) = self.session.as_mut().filter(|session| session.heartbeat) {
+            session.last_beat = now.timestamp;
+        }
+    }
+}
+
+/// Keeping several browser tabs in step, see `backend::pure::tabs`. Only one tab, the leader, has a session, the others
+/// take over what changed elsewhere without reporting it again.
+#[cfg(all(feature = "pure", any(target_arch = "wasm32", not(feature = "native"))))]
+impl<P: Persist> Tracker<P> {
+    /// When the session was begun or last extended, `None` without a session.
+    pub fn session_beat(&self) -> Option<u64> {
+        self.session.as_ref().map(|session| session.last_beat)
+    }
+
+    /// Continues a session another tab began, without reporting a new one.
+    pub fn resume_session(&mut self, last_beat: u64) {
+        if self.session.is_none() && self.has_consent(ConsentFeatures::Sessions) {
+            self.session = Some(Session { last_beat, heartbeat: true });
+        }
+    }
+
+    /// Leaves the session to another tab, without ending it.
+    pub fn suspend_session(&mut self) {
+        self.session = None;
+    }
+
+    /// Switches to an id another tab changed to. With `restart_session`, the session is ended for the old id and begun
+    /// for the new one, like [Tracker::change_device_id] without merging.
+    pub fn adopt_device_id(&mut self, id: &str, device_id_type: DeviceIdType, restart_session: bool, now: Now) {
+        if device_id_type == DeviceIdType::Temporary {
+            self.enable_offline_mode(now);
+            return;
+        }
+        if self.device_id_type == DeviceIdType::Temporary {
+            self.disable_offline_mode(Some(id));
+        } else if restart_session && self.session.is_some() {
+            let heartbeat = self.session.as_ref().map(|session| session.heartbeat).unwrap_or(true);
+            self.end_session(None, now);
+            self.device_id = id.to_owned();
+            self.begin_session(heartbeat, now);
+        } else {
+            self.flush_events(now);
+            self.device_id = id.to_owned();
+        }
+        self.device_id_type = device_id_type;
+    }
+
+    /// Takes over consent another tab already reported.
+    pub fn sync_consent(&mut self, features: &[&str], granted: bool, now: Now) {
+        let features = self.expand_features(features);
+        if granted {
+            self.consents.extend(features);
+        } else {
+            if features.iter().any(|feature| feature == Into::<&'static str>::into(ConsentFeatures::Sessions)) {
+                self.end_session(None, now);
+            }
+            for feature in &features {
+                self.consents.remove(feature);
+            }
+        }
+    }
+}
+
+/// Custom property values as Countly expects them in `user_details`.
+pub(crate) fn value_to_json(value: &Value) -> Json {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(key: &str) -> CustomEvent {
+        CustomEvent { key: key.to_owned(), count: 1, ..Default::default() }
+    }
+
+    #[test]
+    fn reports_location_with_consent() {
+        let mut config = Config::new("key", "http://localhost");
+        config.require_consent = true;
+        config.country_code = Some("DE".to_owned());
+        let mut tracker: Tracker<()> = Tracker::new(config, Queue::new(None, ()), "device".to_owned(), DeviceIdType::SdkGenerated, "test", Map::new());
+        let now = Now { timestamp: 0, hour: 0, dow: 0, tz: 0 };
+        let location = Location { country_code: Some("AT".to_owned()), gps: Some((48.2, 16.37)), ..Default::default() };
+
+        tracker.set_location(location.clone(), now);
+        assert_eq!(tracker.queue.iter().count(), 0);
+        tracker.add_consent(&["location", "sessions"], now);
+        tracker.set_location(location, now);
+        tracker.disable_location(now);
+        tracker.begin_session(false, now);
+
+        let requests: Vec<&Request> = tracker.queue.iter().skip(1).collect();
+        assert_eq!(requests[0].get("location"), Some("48.2,16.37"));
+        assert_eq!(requests[0].get("country_code"), Some("AT"));
+        assert_eq!(requests[1].get("location"), Some(""));
+        assert_eq!(requests[2].get("begin_session"), Some("1"));
+        assert_eq!(requests[2].get("country_code"), None);
+    }
+
+    #[test]
+    fn switches_to_temporary_device_id() {
+        let config = Config::new("key", "http://localhost");
+        let mut tracker: Tracker<()> = Tracker::new(config, Queue::new(None, ()), "device".to_owned(), DeviceIdType::SdkGenerated, "test", Map::new());
+        let now = Now { timestamp: 0, hour: 0, dow: 0, tz: 0 };
+        let device_ids = |tracker: &Tracker<()>| {
+            tracker.queue.iter().map(|request| request.get("device_id").unwrap_or_default().to_owned()).collect::<Vec<_>>()
+        };
+
+        tracker.add_event(event("before"), now);
+        tracker.enable_offline_mode(now);
+        assert_eq!((tracker.device_id(), tracker.device_id_type()), (TEMP_DEVICE_ID, DeviceIdType::Temporary));
+        tracker.disable_offline_mode(None);
+        assert_eq!((tracker.device_id(), tracker.device_id_type()), ("device", DeviceIdType::SdkGenerated));
+
+        tracker.enable_offline_mode(now);
+        tracker.add_event(event("anonymous"), now);
+        tracker.flush_events(now);
+        assert!(tracker.is_offline());
+        assert_eq!(device_ids(&tracker), ["device", TEMP_DEVICE_ID]);
+        tracker.change_device_id("user", DeviceIdChange::WithMerge, now);
+        assert!(!tracker.is_offline());
+        assert_eq!((tracker.device_id(), tracker.device_id_type()), ("user", DeviceIdType::DeveloperSupplied));
+        assert_eq!(device_ids(&tracker), ["device", "user"]);
+
+        tracker.change_device_id("other", DeviceIdChange::WithMerge, now);
+        let merge = tracker.queue.iter().last().unwrap();
+        assert_eq!((merge.get("device_id"), merge.get("old_device_id")), (Some("other"), Some("user")));
+    }
+}
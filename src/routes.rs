@@ -0,0 +1,191 @@
+//! Reporting views and links per route instead of per path, see [Routes].
+
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "regex")]
+use std::fmt;
+
+/// Set with [Config::routes](crate::Config::routes), `None` reports everything unchanged.
+static ROUTES: Mutex<Option<Routes>> = Mutex::new(None);
+
+#[derive(Debug, Clone)]
+enum Rule {
+    /// The segments of a template, see [Routes::route].
+    Template(String, Vec<String>),
+    #[cfg(feature = "regex")]
+    Regex(regex_lite::Regex, String),
+}
+
+impl Rule {
+    /// The route of `path`, if this rule matches it.
+    fn apply(&self, path: &str) -> Option<String> {
+        match self {
+            Self::Template(template, segments) => {
+                let mut parts = path.split('/');
+                for segment in segments {
+                    if segment == "*" {
+                        return Some(template.clone());
+                    }
+                    match parts.next() {
+                        Some(part) if segment.starts_with(':') && !part.is_empty() => {}
+                        Some(part) if part == segment => {}
+                        _ => return None,
+                    }
+                }
+                match parts.next() {
+                    None => Some(template.clone()),
+                    Some(_) => None,
+                }
+            }
+            #[cfg(feature = "regex")]
+            Self::Regex(regex, replacement) => {
+                if regex.is_match(path) {
+                    Some(regex.replace(path, replacement.as_str()).into_owned())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Maps concrete paths like `/users/1234/orders/99` to the routes they belong to, like `/users/:id/orders/:id`, so the
+/// views table doesn't get a row per object. Applied to view names, view URLs and the `href` of link clicks (except
+/// with the Web SDK, which reports link clicks itself).
+///
+/// ```ignore
+/// config.routes = Some(Routes::new()
+///     .route("/users/:id/orders/:id")
+///     .route("/docs/*")
+///     .regex(r"^/files/[0-9a-f]{32}$", "/files/:hash")?);
+/// ```
+///
+/// The rules are tried in the order they were added and the first match wins. Query and fragment are left out of
+/// matched values, everything that no rule matches is reported unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+    rules: Vec<Rule>,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A route of your app. Segments starting with `:` match any segment and `*` at the end matches the rest of the
+    /// path, everything else has to be equal. Matching paths are reported as `template`.
+    pub fn route(mut self, template: &str) -> Self {
+        let template = trim(template).to_owned();
+        let segments = template.split('/').map(str::to_owned).collect();
+        self.rules.push(Rule::Template(template, segments));
+        self
+    }
+
+    /// Paths that `pattern` matches are reported with the match replaced by `replacement`, which can refer to groups
+    /// as `$1` or `$name`. Only with the `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn regex(mut self, pattern: &str, replacement: &str) -> Result<Self, RouteError> {
+        let regex = regex_lite::Regex::new(pattern)
+            .map_err(|err| RouteError::InvalidRegex { pattern: pattern.to_owned(), message: err.to_string() })?;
+        self.rules.push(Rule::Regex(regex, replacement.to_owned()));
+        Ok(self)
+    }
+
+    /// The route of a path or URL, like `https://example.com/users/:id` for `https://example.com/users/12?tab=orders`,
+    /// or `value` if no rule matches.
+    pub fn normalize(&self, value: &str) -> String {
+        let path_start = match value.find("://") {
+            Some(scheme_end) => value[scheme_end + 3..].find('/').map(|slash| scheme_end + 3 + slash).unwrap_or(value.len()),
+            None => 0,
+        };
+        let (origin, rest) = value.split_at(path_start);
+        let path = trim(rest.split(['?', '#']).next().unwrap_or_default());
+        match self.rules.iter().find_map(|rule| rule.apply(path)) {
+            Some(route) => format!("{}{}", origin, route),
+            None => value.to_owned(),
+        }
+    }
+}
+
+/// Without the trailing slash, except for the root.
+fn trim(path: &str) -> &str {
+    match path.strip_suffix('/') {
+        Some(trimmed) if !trimmed.is_empty() => trimmed,
+        _ => path,
+    }
+}
+
+/// Returned by [Routes::regex].
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    InvalidRegex { pattern: String, message: String },
+}
+
+#[cfg(feature = "regex")]
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidRegex { pattern, message } => write!(f, "invalid route pattern {}: {}", pattern, message),
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+impl std::error::Error for RouteError {}
+
+fn lock() -> MutexGuard<'static, Option<Routes>> {
+    ROUTES.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub(crate) fn configure(routes: Option<Routes>) {
+    *lock() = routes;
+}
+
+#[allow(dead_code)] // Only the Web SDK needs to know.
+pub(crate) fn is_configured() -> bool {
+    lock().is_some()
+}
+
+/// `value` as reported with the configured routes.
+pub(crate) fn normalize(value: &str) -> String {
+    match lock().as_ref() {
+        Some(routes) => routes.normalize(value),
+        None => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_paths() {
+        let routes = Routes::new()
+            .route("/users/:id/orders/:id")
+            .route("/users/me")
+            .route("/users/:id")
+            .route("/docs/*");
+        assert_eq!(routes.normalize("/users/1234/orders/99"), "/users/:id/orders/:id");
+        assert_eq!(routes.normalize("/users/1234/orders/99/"), "/users/:id/orders/:id");
+        assert_eq!(routes.normalize("/users/me?tab=orders"), "/users/me");
+        assert_eq!(routes.normalize("/users/12#top"), "/users/:id");
+        assert_eq!(routes.normalize("/users//orders/99"), "/users//orders/99");
+        assert_eq!(routes.normalize("/users/12/invoices"), "/users/12/invoices");
+        assert_eq!(routes.normalize("/docs/intro/install"), "/docs/*");
+        assert_eq!(routes.normalize("https://example.com/users/12?tab=orders"), "https://example.com/users/:id");
+        assert_eq!(routes.normalize("https://example.com"), "https://example.com");
+        assert_eq!(routes.normalize("Checkout"), "Checkout");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn normalizes_with_regex() {
+        let routes = Routes::new()
+            .regex(r"^/files/[0-9a-f]{8}$", "/files/:hash").unwrap()
+            .regex(r"^/(?P<lang>[a-z]{2})/.*", "/$lang/*").unwrap();
+        assert_eq!(routes.normalize("/files/0123abcd"), "/files/:hash");
+        assert_eq!(routes.normalize("/de/about/team"), "/de/*");
+        assert_eq!(routes.normalize("/files/xyz"), "/files/xyz");
+        assert!(matches!(Routes::new().regex("(", ""), Err(RouteError::InvalidRegex { .. })));
+    }
+}
@@ -0,0 +1,147 @@
+//! Manual sessions that measure their own duration, see [Session].
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::backend::{Active, Backend};
+
+/// Whether a [Session] is alive, the SDK only has one session at a time.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The session durations reported so far, in milliseconds since the epoch.
+#[derive(Debug, Clone, Copy)]
+struct Beats {
+    begun: f64,
+    last: f64,
+}
+
+impl Beats {
+    fn new(now: f64) -> Self {
+        Self { begun: now, last: now }
+    }
+
+    /// Seconds since the last beat, which is now.
+    fn beat(&mut self, now: f64) -> f64 {
+        let secs = (now - self.last).max(0.0) / 1000.0;
+        self.last = self.last.max(now);
+        secs
+    }
+}
+
+/// A session controlled by your code instead of [Countly::enable_session_tracking](crate::Countly::enable_session_tracking),
+/// for example one per connection of a server. Begins on creation and ends when dropped or with [Session::end], the
+/// durations are measured instead of passed to [Countly::extend_session](crate::Countly::extend_session) and
+/// [Countly::end_session](crate::Countly::end_session).
+///
+/// ```ignore
+/// let session = Session::begin().expect("no other session");
+/// // ...
+/// session.end();
+/// ```
+///
+/// There is only one session at a time, so while this is alive another one can't be begun.
+#[must_use = "the session ends when this is dropped"]
+#[derive(Debug)]
+pub struct Session {
+    heartbeat: bool,
+    beats: Beats,
+    ended: bool,
+}
+
+impl Session {
+    /// Begins a session that the SDK extends every [Config::session_update](crate::Config::session_update) seconds.
+    /// `None` while another [Session] is alive.
+    pub fn begin() -> Option<Self> {
+        Self::start(true)
+    }
+
+    /// Begins a session that is only extended with [Session::extend]. `None` while another [Session] is alive.
+    pub fn begin_without_heartbeat() -> Option<Self> {
+        Self::start(false)
+    }
+
+    fn start(heartbeat: bool) -> Option<Self> {
+        if ACTIVE.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Active::begin_session(!heartbeat);
+        Some(Self { heartbeat, beats: Beats::new(now()), ended: false })
+    }
+
+    /// Reports the time since the session was begun or last extended. Sessions with heartbeat are extended
+    /// automatically, so this does nothing for them.
+    pub fn extend(&mut self) {
+        if !self.heartbeat {
+            Active::extend_session(self.beats.beat(now()));
+        }
+    }
+
+    /// Seconds since the session was begun.
+    pub fn elapsed(&self) -> f64 {
+        (now() - self.beats.begun).max(0.0) / 1000.0
+    }
+
+    /// Ends the session, reporting the time since it was last extended. Same as dropping it.
+    pub fn end(self) {}
+
+    fn finish(&mut self) {
+        if self.ended {
+            return;
+        }
+        self.ended = true;
+        if self.heartbeat {
+            // Only the SDK knows when it last extended the session.
+            Active::end_session(None);
+        } else {
+            Active::end_session(Some(self.beats.beat(now())));
+        }
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_durations() {
+        let mut beats = Beats::new(10_000.0);
+        assert_eq!(beats.beat(25_000.0), 15.0);
+        assert_eq!(beats.beat(85_500.0), 60.5);
+        // The clock went backwards.
+        assert_eq!(beats.beat(80_000.0), 0.0);
+        assert_eq!(beats.beat(86_500.0), 1.0);
+        assert_eq!(beats.begun, 10_000.0);
+    }
+
+    // Without a configured SDK the native backend ignores the sessions.
+    #[cfg(all(feature = "native", not(all(feature = "pure", target_arch = "wasm32"))))]
+    #[test]
+    fn refuses_a_second_session() {
+        let first = Session::begin_without_heartbeat().unwrap();
+        assert!(Session::begin().is_none());
+        assert!(Session::begin_without_heartbeat().is_none());
+        first.end();
+        let second = Session::begin().unwrap();
+        assert!(Session::begin().is_none());
+        drop(second);
+        assert!(Session::begin().is_some());
+    }
+}
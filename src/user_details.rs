@@ -0,0 +1,262 @@
+//! The user details sent with [Countly::set_user_details](crate::Countly::set_user_details).
+
+use std::{collections::HashMap, fmt};
+use serde::{Serialize, Serializer};
+use crate::countly::Value;
+
+/// Countly doesn't know anyone born before this.
+const MIN_BIRTH_YEAR: u32 = 1900;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gender {
+    Male,
+    Female,
+    /// Sent as given, the dashboard only distinguishes `M` and `F` though.
+    Other(String),
+}
+
+impl Serialize for Gender {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(match self {
+            Self::Male => "M",
+            Self::Female => "F",
+            Self::Other(gender) => gender,
+        })
+    }
+}
+
+impl From<&str> for Gender {
+    /// `M` and `F` (in any case) become [Gender::Male] and [Gender::Female], everything else [Gender::Other].
+    fn from(gender: &str) -> Self {
+        match gender {
+            "M" | "m" => Self::Male,
+            "F" | "f" => Self::Female,
+            gender => Self::Other(gender.to_owned()),
+        }
+    }
+}
+
+/// Why [UserDetails::validate] rejected the details, carrying the offending value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserDetailsError {
+    /// Before 1900 or in the future.
+    BirthYear(u32),
+    Email(String),
+    /// Only digits, spaces, `-`, `.`, parentheses and a leading `+` are allowed, with 4 to 15 digits.
+    Phone(String),
+    /// Must be an `http` or `https` URL.
+    Picture(String),
+}
+
+impl fmt::Display for UserDetailsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BirthYear(year) => write!(f, "invalid birth year {}", year),
+            Self::Email(email) => write!(f, "invalid email address {}", email),
+            Self::Phone(phone) => write!(f, "invalid phone number {}", phone),
+            Self::Picture(url) => write!(f, "invalid picture URL {}", url),
+        }
+    }
+}
+
+impl std::error::Error for UserDetailsError {}
+
+/// Fields that are `None` are left unchanged on the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Default)]
+pub struct UserDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+    /// URL of a profile picture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gender: Option<Gender>,
+    /// Year of birth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byear: Option<u32>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub custom: HashMap<String, Value>,
+}
+
+impl UserDetails {
+    pub fn builder() -> UserDetailsBuilder {
+        UserDetailsBuilder::default()
+    }
+
+    /// Checks the fields with a known format, [Countly::set_user_details](crate::Countly::set_user_details) does this
+    /// before sending.
+    pub fn validate(&self) -> Result<(), UserDetailsError> {
+        if let Some(byear) = self.byear {
+            if byear < MIN_BIRTH_YEAR || byear > current_year() {
+                return Err(UserDetailsError::BirthYear(byear));
+            }
+        }
+        if let Some(email) = &self.email {
+            if !valid_email(email) {
+                return Err(UserDetailsError::Email(email.clone()));
+            }
+        }
+        if let Some(phone) = &self.phone {
+            if !valid_phone(phone) {
+                return Err(UserDetailsError::Phone(phone.clone()));
+            }
+        }
+        if let Some(picture) = &self.picture {
+            if !valid_url(picture) {
+                return Err(UserDetailsError::Picture(picture.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds [UserDetails], validating them in [UserDetailsBuilder::build].
+///
+/// ```ignore
+/// let details = UserDetails::builder()
+///     .name("Jane Doe")
+///     .email("jane@example.com")
+///     .gender(Gender::Female)
+///     .custom("plan", "pro")
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UserDetailsBuilder {
+    details: UserDetails,
+}
+
+impl UserDetailsBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.details.name = Some(name.to_owned());
+        self
+    }
+
+    pub fn username(mut self, username: &str) -> Self {
+        self.details.username = Some(username.to_owned());
+        self
+    }
+
+    pub fn email(mut self, email: &str) -> Self {
+        self.details.email = Some(email.to_owned());
+        self
+    }
+
+    pub fn organization(mut self, organization: &str) -> Self {
+        self.details.organization = Some(organization.to_owned());
+        self
+    }
+
+    pub fn phone(mut self, phone: &str) -> Self {
+        self.details.phone = Some(phone.to_owned());
+        self
+    }
+
+    pub fn picture(mut self, url: &str) -> Self {
+        self.details.picture = Some(url.to_owned());
+        self
+    }
+
+    pub fn gender(mut self, gender: Gender) -> Self {
+        self.details.gender = Some(gender);
+        self
+    }
+
+    pub fn byear(mut self, byear: u32) -> Self {
+        self.details.byear = Some(byear);
+        self
+    }
+
+    /// Adds a custom property.
+    pub fn custom(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.custom.insert(key.to_owned(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Result<UserDetails, UserDetailsError> {
+        self.details.validate()?;
+        Ok(self.details)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn current_year() -> u32 {
+    js_sys::Date::new_0().get_full_year()
+}
+
+/// Off by a few hours around new year, which doesn't matter for validating birth years.
+#[cfg(not(target_arch = "wasm32"))]
+fn current_year() -> u32 {
+    const SECS_PER_YEAR: u64 = 31_556_952;
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    1970 + (secs / SECS_PER_YEAR) as u32
+}
+
+fn valid_email(email: &str) -> bool {
+    let mut parts = email.split('@');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => {
+            !local.is_empty()
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|label| !label.is_empty())
+                && !email.chars().any(char::is_whitespace)
+        }
+        _ => false,
+    }
+}
+
+fn valid_phone(phone: &str) -> bool {
+    let digits = phone.chars().filter(char::is_ascii_digit).count();
+    let number = phone.strip_prefix('+').unwrap_or(phone);
+    (4..=15).contains(&digits) && number.chars().all(|c| c.is_ascii_digit() || " -.()".contains(c))
+}
+
+fn valid_url(url: &str) -> bool {
+    let rest = match url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let host = rest.split(&['/', '?', '#'][..]).next().unwrap_or_default();
+    !host.is_empty() && !url.chars().any(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates() {
+        let details = UserDetails::builder()
+            .name("Jane Doe")
+            .email("jane@example.com")
+            .phone("+43 (1) 234-5678")
+            .picture("https://example.com/jane.png")
+            .gender(Gender::Female)
+            .byear(1990)
+            .custom("logins", 3)
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&details.custom).unwrap(),
+            r#"{"logins":3}"#,
+        );
+        assert_eq!(serde_json::to_value(&details).unwrap()["gender"], "F");
+        assert!(serde_json::to_value(UserDetails::default()).unwrap().as_object().unwrap().is_empty());
+
+        assert_eq!(UserDetails::builder().byear(1850).build(), Err(UserDetailsError::BirthYear(1850)));
+        assert_eq!(UserDetails::builder().byear(current_year() + 1).build(), Err(UserDetailsError::BirthYear(current_year() + 1)));
+        assert_eq!(UserDetails::builder().email("jane@localhost").build(), Err(UserDetailsError::Email("jane@localhost".to_owned())));
+        assert_eq!(UserDetails::builder().phone("call me").build(), Err(UserDetailsError::Phone("call me".to_owned())));
+        assert_eq!(UserDetails::builder().picture("ftp://example.com/a.png").build(), Err(UserDetailsError::Picture("ftp://example.com/a.png".to_owned())));
+    }
+}
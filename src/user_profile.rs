@@ -0,0 +1,180 @@
+//! Updating several custom user properties at once, see [UserProfileUpdate].
+
+use std::fmt;
+use crate::{
+    countly::{UserDataOp, Value},
+    profile_cache,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserProfileError {
+    /// Two operations on the same property that would override each other, like `set` and `increment`. Only `push`,
+    /// `push_unique` and `pull` can be repeated.
+    Conflict { key: String, first: &'static str, second: &'static str },
+}
+
+impl fmt::Display for UserProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Conflict { key, first, second } => {
+                write!(f, "conflicting operations on user property {}: {} and {}", key, first, second)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UserProfileError {}
+
+/// Collects modifications of custom user properties and sends them together, instead of calling the `user_data_*`
+/// functions of [Countly](crate::Countly) followed by [Countly::user_data_save](crate::Countly::user_data_save).
+///
+/// ```ignore
+/// UserProfileUpdate::new()
+///     .set("plan", "pro")
+///     .increment("upgrades")
+///     .push("features", "export")
+///     .commit()?;
+/// ```
+///
+/// Dropping an update that wasn't committed (or [discarded](UserProfileUpdate::discard)) prints a warning, since the
+/// modifications are lost.
+#[must_use = "nothing is sent until the update is committed"]
+#[derive(Default)]
+pub struct UserProfileUpdate {
+    ops: Vec<(String, UserDataOp)>,
+    conflict: Option<UserProfileError>,
+}
+
+impl UserProfileUpdate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn op(mut self, key: &str, op: UserDataOp) -> Self {
+        if self.conflict.is_none() {
+            let previous = self.ops.iter()
+                .find(|(previous, previous_op)| previous == key && !(previous_op.accumulates() && previous_op.name() == op.name()));
+            if let Some((_, previous)) = previous {
+                self.conflict = Some(UserProfileError::Conflict { key: key.to_owned(), first: previous.name(), second: op.name() });
+            }
+        }
+        self.ops.push((key.to_owned(), op));
+        self
+    }
+
+    /// Set custom property.
+    pub fn set(self, key: &str, value: impl Into<Value>) -> Self {
+        self.op(key, UserDataOp::Set(value.into()))
+    }
+
+    /// Remove custom property.
+    pub fn unset(self, key: &str) -> Self {
+        self.op(key, UserDataOp::Unset)
+    }
+
+    /// Set custom property only if property does not exist.
+    pub fn set_once(self, key: &str, value: impl Into<Value>) -> Self {
+        self.op(key, UserDataOp::SetOnce(value.into()))
+    }
+
+    /// Increment value in key by one.
+    pub fn increment(self, key: &str) -> Self {
+        self.op(key, UserDataOp::Increment)
+    }
+
+    /// Increment value in key by provided value.
+    pub fn increment_by(self, key: &str, value: f64) -> Self {
+        self.op(key, UserDataOp::IncrementBy(value))
+    }
+
+    /// Multiply value in key by provided value.
+    pub fn multiply(self, key: &str, value: f64) -> Self {
+        self.op(key, UserDataOp::Multiply(value))
+    }
+
+    /// Save max value between current and provided.
+    pub fn max(self, key: &str, value: f64) -> Self {
+        self.op(key, UserDataOp::Max(value))
+    }
+
+    /// Save min value between current and provided.
+    pub fn min(self, key: &str, value: f64) -> Self {
+        self.op(key, UserDataOp::Min(value))
+    }
+
+    /// Add value to key as array element.
+    pub fn push(self, key: &str, value: impl Into<Value>) -> Self {
+        self.op(key, UserDataOp::Push(value.into()))
+    }
+
+    /// Add value to key as array element, but only store unique values in array.
+    pub fn push_unique(self, key: &str, value: impl Into<Value>) -> Self {
+        self.op(key, UserDataOp::PushUnique(value.into()))
+    }
+
+    /// Remove value from array under property with key as name.
+    pub fn pull(self, key: &str, value: impl Into<Value>) -> Self {
+        self.op(key, UserDataOp::Pull(value.into()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Sends all modifications in one request. If any of them conflict, nothing is sent.
+    pub fn commit(mut self) -> Result<(), UserProfileError> {
+        if let Some(conflict) = self.conflict.take() {
+            self.ops.clear();
+            return Err(conflict);
+        }
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        for (key, op) in self.ops.drain(..) {
+            profile_cache::user_data(&key, op);
+        }
+        profile_cache::user_data_save();
+        Ok(())
+    }
+
+    /// Drops the modifications without sending them or warning about it.
+    pub fn discard(mut self) {
+        self.ops.clear();
+    }
+}
+
+impl Drop for UserProfileUpdate {
+    fn drop(&mut self) {
+        if !self.ops.is_empty() {
+            warn(&format!("[Countly] A user profile update with {} modifications was dropped without being committed", self.ops.len()));
+        }
+    }
+}
+
+fn warn(msg: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::warn_1(&msg.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_conflicts() {
+        let update = UserProfileUpdate::new()
+            .push("tags", "a")
+            .push("tags", "b")
+            .set("plan", "pro")
+            .increment("logins");
+        assert!(update.conflict.is_none());
+        update.discard();
+
+        let conflict = UserProfileUpdate::new().set("plan", "pro").push("tags", "a").increment("plan").unset("plan").commit();
+        assert_eq!(conflict, Err(UserProfileError::Conflict { key: "plan".to_owned(), first: "set", second: "increment" }));
+        let conflict = UserProfileUpdate::new().push("tags", "a").pull("tags", "b").commit();
+        assert_eq!(conflict, Err(UserProfileError::Conflict { key: "tags".to_owned(), first: "push", second: "pull" }));
+    }
+}
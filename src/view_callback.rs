@@ -0,0 +1,130 @@
+//! Keeping the callbacks of [Countly::set_view_name_callback](crate::Countly::set_view_name_callback) and
+//! [Countly::set_view_url_callback](crate::Countly::set_view_url_callback) alive, see [ViewCallbackHandle].
+
+use std::{cell::RefCell, marker::PhantomData, rc::Rc};
+use crate::{
+    backend::{Active, Backend},
+    countly::Unsupported,
+    routes,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Name,
+    Url,
+}
+
+/// Shared between the stack and the backend, so [ViewCallbackHandle::replace] doesn't have to set it again.
+type Callback = Rc<RefCell<Box<dyn FnMut() -> String>>>;
+
+/// The callbacks of all live handles, the last one of each kind is set.
+#[derive(Default)]
+struct Stacks {
+    next_id: u64,
+    name: Vec<(u64, Callback)>,
+    url: Vec<(u64, Callback)>,
+}
+
+impl Stacks {
+    fn get_mut(&mut self, kind: Kind) -> &mut Vec<(u64, Callback)> {
+        match kind {
+            Kind::Name => &mut self.name,
+            Kind::Url => &mut self.url,
+        }
+    }
+}
+
+thread_local! {
+    static STACKS: RefCell<Stacks> = RefCell::new(Stacks::default());
+}
+
+/// Sets `callback` in the backend, or restores its own getter for `None`.
+fn install(kind: Kind, callback: Option<&Callback>) -> Result<(), Unsupported> {
+    let callback = callback.map(|callback| {
+        let callback = callback.clone();
+        Box::new(move || routes::normalize(&(callback.borrow_mut())())) as Box<dyn FnMut() -> String>
+    });
+    match kind {
+        Kind::Name => Active::set_view_name_callback(callback),
+        Kind::Url => Active::set_view_url_callback(callback),
+    }
+}
+
+fn push(kind: Kind, callback: Box<dyn FnMut() -> String>) -> Result<ViewCallbackHandle, Unsupported> {
+    let callback = Rc::new(RefCell::new(callback));
+    install(kind, Some(&callback))?;
+    let id = STACKS.with(|stacks| {
+        let mut stacks = stacks.borrow_mut();
+        stacks.next_id += 1;
+        let id = stacks.next_id;
+        stacks.get_mut(kind).push((id, callback));
+        id
+    });
+    Ok(ViewCallbackHandle { kind, id, thread: PhantomData })
+}
+
+pub(crate) fn set_view_name(callback: Box<dyn FnMut() -> String>) -> Result<ViewCallbackHandle, Unsupported> {
+    push(Kind::Name, callback)
+}
+
+pub(crate) fn set_view_url(callback: Box<dyn FnMut() -> String>) -> Result<ViewCallbackHandle, Unsupported> {
+    push(Kind::Url, callback)
+}
+
+/// Keeps a view name or URL callback set until it's dropped. Then the callback that was set before is used again, or
+/// the default of the SDK if there was none.
+///
+/// Handles may be dropped in any order: while a newer one is alive, dropping an older one only forgets its callback.
+/// They have to stay on the thread that set the callback, like the callback itself.
+///
+/// ```ignore
+/// let handle = Countly::set_view_name_callback(|| "Home".to_owned())?;
+/// handle.replace(|| "Start".to_owned());
+/// drop(handle);
+/// ```
+#[must_use = "the callback is removed when this is dropped"]
+#[derive(Debug)]
+pub struct ViewCallbackHandle {
+    kind: Kind,
+    id: u64,
+    /// Not `Send`, the callbacks are kept per thread.
+    thread: PhantomData<Rc<()>>,
+}
+
+impl ViewCallbackHandle {
+    /// Swaps the callback of this handle. Takes effect right away if it's the newest handle, otherwise once the newer
+    /// ones are dropped.
+    pub fn replace(&self, callback: impl FnMut() -> String + 'static) {
+        let current = STACKS.with(|stacks| {
+            stacks.borrow_mut().get_mut(self.kind).iter()
+                .find(|(id, _)| *id == self.id)
+                .map(|(_, callback)| callback.clone())
+        });
+        if let Some(current) = current {
+            *current.borrow_mut() = Box::new(callback);
+        }
+    }
+
+    /// Removes the callback. Same as dropping the handle.
+    pub fn remove(self) {}
+}
+
+impl Drop for ViewCallbackHandle {
+    fn drop(&mut self) {
+        let restore = STACKS.with(|stacks| {
+            let mut stacks = stacks.borrow_mut();
+            let stack = stacks.get_mut(self.kind);
+            let newest = stack.last().map(|(id, _)| *id == self.id).unwrap_or(false);
+            stack.retain(|(id, _)| *id != self.id);
+            if newest {
+                Some(stack.last().map(|(_, callback)| callback.clone()))
+            } else {
+                None
+            }
+        });
+        if let Some(previous) = restore {
+            // Setting it worked before, so this does as well.
+            let _ = install(self.kind, previous.as_ref());
+        }
+    }
+}